@@ -0,0 +1,135 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use image::{ImageBuffer, Rgb, Rgb32FImage};
+
+use crate::integrator::SplitRadiance;
+use crate::sampling::Rng;
+use crate::scene::Scene;
+use crate::vector::Vec3;
+use crate::RayHitProperties;
+
+// Auxiliary buffers that can be rendered along with the image to inspect a scene. Geometric ones
+// come from the ray through the pixel center and are 0 where nothing is hit
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Aov {
+    Depth,      // Distance along the camera ray
+    Normal,     // World space, in [-1, 1]
+    Position,   // World space hit point
+    ObjectId,   // Random color per object
+    MaterialId, // Random color per distinct material
+    Direct,     // Light from the first hit and lights seen by the camera
+    Indirect,   // Light that bounced around the scene before
+}
+
+impl Aov {
+    // Whether the buffer holds light to tone map like the image, rather than data
+    pub fn is_color(&self) -> bool {
+        matches!(self, Aov::Direct | Aov::Indirect)
+    }
+
+    // Fits a data buffer into [0, 1] for 8-bit formats, which would clamp it otherwise. Normals
+    // are mapped from [-1, 1], depths and positions from the range each channel spans
+    pub fn to_unit_range(&self, buffer: &Rgb32FImage) -> Rgb32FImage {
+        let (offset, scale) = match self {
+            Aov::Normal => ([-1.; 3], [0.5; 3]),
+            Aov::Depth | Aov::Position => {
+                let mut min = [f32::INFINITY; 3];
+                let mut max = [f32::NEG_INFINITY; 3];
+                for pixel in buffer.pixels() {
+                    for channel in 0..3 {
+                        min[channel] = min[channel].min(pixel[channel]);
+                        max[channel] = max[channel].max(pixel[channel]);
+                    }
+                }
+                // A channel without any spread ends up all black
+                let scale = |channel: usize| {
+                    let spread = max[channel] - min[channel];
+                    if spread > 0. {
+                        1. / spread
+                    } else {
+                        0.
+                    }
+                };
+                (min, [scale(0), scale(1), scale(2)])
+            }
+            _ => ([0.; 3], [1.; 3]),
+        };
+
+        ImageBuffer::from_fn(buffer.width(), buffer.height(), |x, y| {
+            let pixel = buffer.get_pixel(x, y);
+            Rgb([0, 1, 2].map(|channel| (pixel[channel] - offset[channel]) * scale[channel]))
+        })
+    }
+
+    // Where to save the buffer next to the image, e.g. render.depth.exr for render.exr
+    pub fn path<P: AsRef<Path>>(&self, image_path: P) -> PathBuf {
+        let image_path = image_path.as_ref();
+        let mut file_name = image_path.file_stem().unwrap_or_default().to_owned();
+        file_name.push(format!(".{self}"));
+        if let Some(extension) = image_path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        image_path.with_file_name(file_name)
+    }
+
+    pub(crate) fn value(
+        &self,
+        scene: &Scene,
+        hit: Option<&RayHitProperties>,
+        radiance: &SplitRadiance,
+    ) -> Vec3<f64> {
+        match (self, hit) {
+            (Aov::Direct, _) => radiance.direct,
+            (Aov::Indirect, _) => radiance.indirect,
+            (_, None) => Vec3::new(0., 0., 0.),
+            (Aov::Depth, Some(hit)) => Vec3::new(hit.ray_length, hit.ray_length, hit.ray_length),
+            (Aov::Normal, Some(hit)) => hit.norm_dir,
+            (Aov::Position, Some(hit)) => hit.hit_point,
+            (Aov::ObjectId, Some(hit)) => id_color(hit.object_id),
+            (Aov::MaterialId, Some(hit)) => id_color(scene.material_id(hit.object_id)),
+        }
+    }
+}
+
+// Neighbouring IDs get unrelated colors so that they stand out from each other
+fn id_color(id: usize) -> Vec3<f64> {
+    let mut rng = Rng::new(id as u64);
+    Vec3::new(rng.next_f64(), rng.next_f64(), rng.next_f64())
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "depth" => Ok(Aov::Depth),
+            "normal" => Ok(Aov::Normal),
+            "position" => Ok(Aov::Position),
+            "object_id" => Ok(Aov::ObjectId),
+            "material_id" => Ok(Aov::MaterialId),
+            "direct" => Ok(Aov::Direct),
+            "indirect" => Ok(Aov::Indirect),
+            _ => Err(format!(
+                "unknown AOV `{name}`, expected depth, normal, position, object_id, material_id, \
+                 direct or indirect"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        })
+    }
+}
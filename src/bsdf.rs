@@ -0,0 +1,518 @@
+use std::f64::consts::PI;
+
+use crate::material::{Fresnel, Material, Pbr, Phong};
+use crate::media::Interface;
+use crate::objects;
+use crate::sampling::{cosine_hemisphere, cosine_power_hemisphere, orthonormal_basis, Rng};
+use crate::vector::{Normalize, Vec3};
+
+// GGX alpha never goes below this, since a perfectly smooth microfacet distribution is a delta
+const MIN_ALPHA: f64 = 1e-3;
+
+// How a surface scatters light arriving along dir, with light_dir pointing away from the surface
+// towards where the light comes from
+pub trait Bsdf {
+    // Shading normal on the side the ray arrived from
+    fn norm_dir(&self) -> Vec3<f64>;
+
+    // Whether eval can be nonzero, which makes sampling lights worthwhile
+    fn is_glossy(&self) -> bool;
+
+    // Scattering towards the ray without the perfectly specular parts, which no light sample can
+    // ever hit
+    fn eval(&self, light_dir: Vec3<f64>) -> Vec3<f64>;
+
+    // eval without the share that specular_samples already trace, for light that their rays can
+    // reach as well
+    fn eval_untraced(&self, light_dir: Vec3<f64>) -> Vec3<f64> {
+        self.eval(light_dir)
+    }
+
+    // Density of sample producing light_dir
+    fn pdf(&self, light_dir: Vec3<f64>) -> f64;
+
+    // Picks a direction to continue a path in. None absorbs the path
+    fn sample(&self, rng: &mut Rng) -> Option<BsdfSample>;
+
+    // Reflection and refraction with the share of light they carry, which the Whitted integrator
+    // traces instead of sampling. They are perfect unless the material is rough
+    fn specular_samples(&self, rng: &mut Rng) -> Vec<BsdfSample>;
+
+    // Share of a uniformly bright surrounding that eval reflects back, for ambient light
+    fn reflectance(&self) -> Vec3<f64>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BsdfSample {
+    pub dir: Vec3<f64>,
+    pub weight: Vec3<f64>, // BSDF times cosine over density, what throughput gets multiplied by
+    pub pdf: Option<f64>,  // None for perfect reflection and refraction
+    pub refracted: bool,   // Whether dir crosses into the other medium
+}
+
+impl Material {
+    // Scattering of a ray hitting the surface with the given shading normal through an interface
+    pub fn bsdf(
+        &self,
+        dir: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        interface: &Interface,
+    ) -> Box<dyn Bsdf> {
+        match self {
+            Material::Phong(phong) => Box::new(PhongBsdf::new(phong, dir, norm_dir, interface)),
+            Material::Pbr(pbr) => Box::new(Ggx::new(pbr, dir, norm_dir, interface)),
+        }
+    }
+}
+
+fn facing(dir: Vec3<f64>, norm_dir: Vec3<f64>) -> Vec3<f64> {
+    if dir * norm_dir < 0. {
+        norm_dir
+    } else {
+        -norm_dir
+    }
+}
+
+fn uniform(x: f64) -> Vec3<f64> {
+    Vec3::new(x, x, x)
+}
+
+fn max_channel(color: Vec3<f64>) -> f64 {
+    color[0].max(color[1]).max(color[2])
+}
+
+// Reflective and refractive albedos once Fresnel and total internal reflection have moved light
+// from one to the other, along with the refracted direction if there is one
+fn split_albedos(
+    material: &Phong,
+    dir: Vec3<f64>,
+    norm_dir: Vec3<f64>,
+    interface: &Interface,
+) -> (f64, f64, Option<Vec3<f64>>) {
+    let mut reflective_albedo = material.reflective_albedo;
+    let mut refractive_albedo = material.refractive_albedo;
+
+    let refract_dir = objects::refract(dir, norm_dir, interface.n1, interface.n2);
+    match (refract_dir, material.fresnel) {
+        (None, _) => {
+            // Total internal reflection, nothing gets through
+            reflective_albedo += refractive_albedo;
+            refractive_albedo = 0.;
+        }
+        (Some(_), Some(fresnel)) => {
+            let cos_incident = (dir * norm_dir).abs();
+            let reflectance = fresnel.reflectance(cos_incident, interface.n1, interface.n2);
+
+            reflective_albedo += refractive_albedo * reflectance;
+            refractive_albedo *= 1. - reflectance;
+        }
+        (Some(_), None) => {}
+    }
+
+    (reflective_albedo, refractive_albedo, refract_dir)
+}
+
+// The diffuse and specular terms of a Phong material as an energy conserving BSDF: a Lambertian
+// lobe and a normalized Phong lobe around the mirror direction, next to perfect reflection and
+// refraction
+pub struct PhongBsdf {
+    norm_dir: Vec3<f64>,
+    mirror_dir: Vec3<f64>,
+    refract_dir: Option<Vec3<f64>>,
+    diffuse: Vec3<f64>,
+    specular_albedo: f64,
+    specular_exponent: f64,
+    reflective_albedo: f64,
+    refractive_albedo: f64,
+    roughness: f64,
+    albedo: f64, // Of all the lobes together
+    // Chances of sampling the lobes, the rest goes to reflection and refraction
+    diffuse_chance: f64,
+    specular_chance: f64,
+}
+
+impl PhongBsdf {
+    pub fn new(
+        material: &Phong,
+        dir: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        interface: &Interface,
+    ) -> Self {
+        let (reflective_albedo, refractive_albedo, refract_dir) =
+            split_albedos(material, dir, norm_dir, interface);
+        let norm_dir = facing(dir, norm_dir);
+        let diffuse = material.diffuse_color * material.diffuse_albedo;
+        let diffuse_albedo = max_channel(diffuse);
+        let albedo =
+            diffuse_albedo + material.specular_albedo + reflective_albedo + refractive_albedo;
+
+        // Materials that return more light than they receive are scaled down so that paths
+        // can't gain energy
+        let scale = 1. / albedo.max(1.);
+        let chance = |weight: f64| if albedo > 0. { weight / albedo } else { 0. };
+
+        Self {
+            norm_dir,
+            mirror_dir: objects::reflect(dir, norm_dir).normalize(),
+            refract_dir: refract_dir.map(|i| i.normalize()),
+            diffuse: diffuse * scale,
+            specular_albedo: material.specular_albedo * scale,
+            specular_exponent: material.specular_exponent,
+            reflective_albedo,
+            refractive_albedo,
+            roughness: material.roughness,
+            albedo,
+            diffuse_chance: chance(diffuse_albedo),
+            specular_chance: chance(material.specular_albedo),
+        }
+    }
+
+    fn sample_diffuse(&self, rng: &mut Rng) -> Vec3<f64> {
+        let local = cosine_hemisphere(rng.next_f64(), rng.next_f64());
+        let (tangent, bitangent) = orthonormal_basis(self.norm_dir);
+        tangent * local[0] + bitangent * local[1] + self.norm_dir * local[2]
+    }
+
+    fn sample_specular(&self, rng: &mut Rng) -> Vec3<f64> {
+        let local = cosine_power_hemisphere(rng.next_f64(), rng.next_f64(), self.specular_exponent);
+        let (tangent, bitangent) = orthonormal_basis(self.mirror_dir);
+        tangent * local[0] + bitangent * local[1] + self.mirror_dir * local[2]
+    }
+
+    // Reflection or refraction with the weight it carries, spread by the roughness. None when
+    // the spread pushes it to the wrong side of the surface
+    fn specular_sample(&self, refracted: bool, weight: f64, rng: &mut Rng) -> Option<BsdfSample> {
+        let ideal = if refracted {
+            self.refract_dir?
+        } else {
+            self.mirror_dir
+        };
+        let dir = if self.roughness > 0. {
+            objects::jitter(ideal, self.roughness, rng.next_f64(), rng.next_f64())
+        } else {
+            ideal
+        };
+        if (dir * self.norm_dir > 0.) == refracted {
+            return None;
+        }
+
+        Some(BsdfSample {
+            dir,
+            weight: uniform(weight),
+            pdf: None,
+            refracted,
+        })
+    }
+}
+
+impl Bsdf for PhongBsdf {
+    fn norm_dir(&self) -> Vec3<f64> {
+        self.norm_dir
+    }
+
+    fn is_glossy(&self) -> bool {
+        self.diffuse_chance > 0. || self.specular_chance > 0.
+    }
+
+    fn eval(&self, light_dir: Vec3<f64>) -> Vec3<f64> {
+        if light_dir * self.norm_dir <= 0. {
+            return Vec3::new(0., 0., 0.);
+        }
+
+        let cos_mirror = (light_dir * self.mirror_dir).max(0.);
+        let specular = self.specular_albedo * (self.specular_exponent + 2.) / (2. * PI)
+            * cos_mirror.powf(self.specular_exponent);
+        self.diffuse * (1. / PI) + uniform(specular)
+    }
+
+    // Each of sample_diffuse and sample_specular picked with its chance
+    fn pdf(&self, light_dir: Vec3<f64>) -> f64 {
+        let cos = light_dir * self.norm_dir;
+        if cos <= 0. {
+            return 0.;
+        }
+
+        let cos_mirror = (light_dir * self.mirror_dir).max(0.);
+        self.diffuse_chance * cos / PI
+            + self.specular_chance * (self.specular_exponent + 1.) / (2. * PI)
+                * cos_mirror.powf(self.specular_exponent)
+    }
+
+    // Picks one lobe in proportion to how much light it carries
+    fn sample(&self, rng: &mut Rng) -> Option<BsdfSample> {
+        if self.albedo <= 0. {
+            return None;
+        }
+
+        let choice = rng.next_f64();
+        if choice < self.diffuse_chance + self.specular_chance {
+            let dir = if choice < self.diffuse_chance {
+                self.sample_diffuse(rng)
+            } else {
+                self.sample_specular(rng)
+            };
+            let cos = dir * self.norm_dir;
+            let pdf = self.pdf(dir);
+            if cos <= 0. || pdf <= 0. {
+                return None;
+            }
+
+            return Some(BsdfSample {
+                dir,
+                weight: self.eval(dir) * (cos / pdf),
+                pdf: Some(pdf),
+                refracted: false,
+            });
+        }
+
+        // Reflection or refraction, whose albedo cancels out with its chance
+        let delta_choice = (1. - choice) * self.albedo;
+        let refracted = self.refract_dir.is_some() && delta_choice < self.refractive_albedo;
+        self.specular_sample(refracted, self.albedo.min(1.), rng)
+    }
+
+    // The albedos as they are, without the energy conserving scale
+    fn specular_samples(&self, rng: &mut Rng) -> Vec<BsdfSample> {
+        let mut samples = Vec::new();
+        if self.reflective_albedo > 0. {
+            samples.extend(self.specular_sample(false, self.reflective_albedo, rng));
+        }
+        if self.refractive_albedo > 0. {
+            samples.extend(self.specular_sample(true, self.refractive_albedo, rng));
+        }
+        samples
+    }
+
+    fn reflectance(&self) -> Vec3<f64> {
+        self.diffuse + uniform(self.specular_albedo)
+    }
+}
+
+// A metallic/roughness material: GGX specular reflection with Smith shadowing, over a Lambertian
+// base and a transmissive one. Metals reflect with the base color as their Fresnel color at
+// normal incidence, dielectrics with what their refractive index gives
+pub struct Ggx {
+    norm_dir: Vec3<f64>,
+    view_dir: Vec3<f64>, // Back along the ray
+    base_color: Vec3<f64>,
+    metallic: f64,
+    alpha: f64,
+    mirror_share: f64, // Of specular reflection that Whitted traces as a mirror ray
+    diffuse: Vec3<f64>,
+    transmission: Vec3<f64>,
+    // Thin surfaces still reflect like their material, though light passes them undeflected
+    fresnel_indices: (f64, f64),
+    refraction_indices: (f64, f64),
+    diffuse_chance: f64,
+    specular_chance: f64, // The rest goes to transmission
+}
+
+impl Ggx {
+    pub fn new(material: &Pbr, dir: Vec3<f64>, norm_dir: Vec3<f64>, interface: &Interface) -> Self {
+        let norm_dir = facing(dir, norm_dir);
+        let view_dir = -dir.normalize();
+        let roughness = material.roughness.clamp(0., 1.);
+        let metallic = material.metallic.clamp(0., 1.);
+        let transmission = material.transmission.clamp(0., 1.);
+
+        let fresnel_indices = if interface.n1 == interface.n2 {
+            (interface.n1, material.ior)
+        } else {
+            (interface.n1, interface.n2)
+        };
+        let base = material.base_color * (1. - metallic);
+
+        let mut bsdf = Self {
+            norm_dir,
+            view_dir,
+            base_color: material.base_color,
+            metallic,
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+            mirror_share: (1. - roughness).powi(2),
+            diffuse: base * (1. - transmission),
+            transmission: base * transmission,
+            fresnel_indices,
+            refraction_indices: (interface.n1, interface.n2),
+            diffuse_chance: 0.,
+            specular_chance: 0.,
+        };
+
+        let dielectric = bsdf.dielectric_fresnel(view_dir * norm_dir);
+        let specular = metallic + (1. - metallic) * dielectric;
+        let diffuse = (1. - dielectric) * max_channel(bsdf.diffuse);
+        let total = specular + diffuse + (1. - dielectric) * max_channel(bsdf.transmission);
+        if total > 0. {
+            bsdf.diffuse_chance = diffuse / total;
+            bsdf.specular_chance = specular / total;
+        }
+        bsdf
+    }
+
+    fn dielectric_fresnel(&self, cos: f64) -> f64 {
+        let (n1, n2) = self.fresnel_indices;
+        Fresnel::Schlick.reflectance(cos.clamp(0., 1.), n1, n2)
+    }
+
+    // Metals and dielectrics mixed by metallic
+    fn fresnel(&self, cos: f64) -> Vec3<f64> {
+        let metal = self.base_color + (uniform(1.) - self.base_color) * (1. - cos).powi(5);
+        metal * self.metallic + uniform(self.dielectric_fresnel(cos) * (1. - self.metallic))
+    }
+
+    // Microfacet normal distribution
+    fn distribution(&self, cos: f64) -> f64 {
+        let alpha2 = self.alpha * self.alpha;
+        let denominator = cos * cos * (alpha2 - 1.) + 1.;
+        alpha2 / (PI * denominator * denominator)
+    }
+
+    // Smith masking of one direction
+    fn masking(&self, cos: f64) -> f64 {
+        let alpha2 = self.alpha * self.alpha;
+        2. * cos / (cos + (alpha2 + (1. - alpha2) * cos * cos).sqrt())
+    }
+
+    // Microfacet normal distributed by distribution times its cosine
+    fn sample_half_vector(&self, rng: &mut Rng) -> Vec3<f64> {
+        let (u, v) = (rng.next_f64(), rng.next_f64());
+        let alpha2 = self.alpha * self.alpha;
+        let cos = ((1. - u) / (1. + (alpha2 - 1.) * u)).sqrt();
+        let sin = (1. - cos * cos).max(0.).sqrt();
+        let rotation = 2. * PI * v;
+        let (tangent, bitangent) = orthonormal_basis(self.norm_dir);
+        (tangent * rotation.cos() + bitangent * rotation.sin()) * sin + self.norm_dir * cos
+    }
+
+    // Diffuse and specular lobes, with the specular one scaled
+    fn eval_scaled(&self, light_dir: Vec3<f64>, specular_scale: f64) -> Vec3<f64> {
+        let cos_light = light_dir * self.norm_dir;
+        let cos_view = self.view_dir * self.norm_dir;
+        if cos_light <= 0. || cos_view <= 0. {
+            return Vec3::new(0., 0., 0.);
+        }
+
+        let half = (light_dir + self.view_dir).normalize();
+        let cos_half = (self.view_dir * half).max(0.);
+        let specular = self.distribution(half * self.norm_dir)
+            * self.masking(cos_view)
+            * self.masking(cos_light)
+            / (4. * cos_light * cos_view);
+
+        self.fresnel(cos_half) * (specular * specular_scale)
+            + self.diffuse * ((1. - self.dielectric_fresnel(cos_half)) / PI)
+    }
+
+    fn reflected_sample(&self, dir: Vec3<f64>) -> Option<BsdfSample> {
+        let cos = dir * self.norm_dir;
+        let pdf = self.pdf(dir);
+        if cos <= 0. || pdf <= 0. {
+            return None;
+        }
+        Some(BsdfSample {
+            dir,
+            weight: self.eval(dir) * (cos / pdf),
+            pdf: Some(pdf),
+            refracted: false,
+        })
+    }
+}
+
+impl Bsdf for Ggx {
+    fn norm_dir(&self) -> Vec3<f64> {
+        self.norm_dir
+    }
+
+    fn is_glossy(&self) -> bool {
+        self.diffuse_chance > 0. || self.specular_chance > 0.
+    }
+
+    fn eval(&self, light_dir: Vec3<f64>) -> Vec3<f64> {
+        self.eval_scaled(light_dir, 1.)
+    }
+
+    fn eval_untraced(&self, light_dir: Vec3<f64>) -> Vec3<f64> {
+        self.eval_scaled(light_dir, 1. - self.mirror_share)
+    }
+
+    fn pdf(&self, light_dir: Vec3<f64>) -> f64 {
+        let cos = light_dir * self.norm_dir;
+        if cos <= 0. {
+            return 0.;
+        }
+
+        let half = (light_dir + self.view_dir).normalize();
+        let cos_half = self.view_dir * half;
+        let specular = if cos_half > 0. {
+            self.distribution(half * self.norm_dir) * (half * self.norm_dir) / (4. * cos_half)
+        } else {
+            0.
+        };
+        self.diffuse_chance * cos / PI + self.specular_chance * specular
+    }
+
+    fn sample(&self, rng: &mut Rng) -> Option<BsdfSample> {
+        let choice = rng.next_f64();
+        if choice < self.diffuse_chance {
+            let local = cosine_hemisphere(rng.next_f64(), rng.next_f64());
+            let (tangent, bitangent) = orthonormal_basis(self.norm_dir);
+            return self.reflected_sample(
+                tangent * local[0] + bitangent * local[1] + self.norm_dir * local[2],
+            );
+        }
+
+        let half = self.sample_half_vector(rng);
+        if choice < self.diffuse_chance + self.specular_chance {
+            return self.reflected_sample(objects::reflect(-self.view_dir, half));
+        }
+
+        // Refraction through the sampled microfacet. Its spread isn't known as a density, so
+        // lights are only ever reached through it by chance
+        let cos_half = self.view_dir * half;
+        let transmission_chance = 1. - self.diffuse_chance - self.specular_chance;
+        if cos_half <= 0. || transmission_chance <= 0. {
+            return None;
+        }
+        let (n1, n2) = self.refraction_indices;
+        let dir = objects::refract(-self.view_dir, half, n1, n2)?.normalize();
+        if dir * self.norm_dir >= 0. {
+            return None;
+        }
+
+        Some(BsdfSample {
+            dir,
+            weight: self.transmission
+                * ((1. - self.dielectric_fresnel(cos_half)) / transmission_chance),
+            pdf: None,
+            refracted: true,
+        })
+    }
+
+    // Only smooth surfaces reflect a sharp image, rougher ones leave their reflections to eval
+    fn specular_samples(&self, _rng: &mut Rng) -> Vec<BsdfSample> {
+        let cos = self.view_dir * self.norm_dir;
+        let mut samples = vec![BsdfSample {
+            dir: objects::reflect(-self.view_dir, self.norm_dir).normalize(),
+            weight: self.fresnel(cos) * self.mirror_share,
+            pdf: None,
+            refracted: false,
+        }];
+
+        let (n1, n2) = self.refraction_indices;
+        if let Some(dir) = objects::refract(-self.view_dir, self.norm_dir, n1, n2) {
+            samples.push(BsdfSample {
+                dir: dir.normalize(),
+                weight: self.transmission * (1. - self.dielectric_fresnel(cos)),
+                pdf: None,
+                refracted: true,
+            });
+        }
+        samples.retain(|i| i.weight != Vec3::new(0., 0., 0.));
+        samples
+    }
+
+    fn reflectance(&self) -> Vec3<f64> {
+        let cos = self.view_dir * self.norm_dir;
+        self.fresnel(cos) * (1. - self.mirror_share)
+            + self.diffuse * (1. - self.dielectric_fresnel(cos))
+    }
+}
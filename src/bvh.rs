@@ -0,0 +1,245 @@
+use crate::objects::{Intersection, RayIntersect};
+use crate::vector::Vec3;
+
+const MAX_LEAF_SIZE: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3<f64>,
+    pub max: Vec3<f64>,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3<f64>, max: Vec3<f64>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Self::new(
+            Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        )
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self::new(
+            Vec3::new(
+                self.min[0].min(other.min[0]),
+                self.min[1].min(other.min[1]),
+                self.min[2].min(other.min[2]),
+            ),
+            Vec3::new(
+                self.max[0].max(other.max[0]),
+                self.max[1].max(other.max[1]),
+                self.max[2].max(other.max[2]),
+            ),
+        )
+    }
+
+    pub fn centroid(&self) -> Vec3<f64> {
+        (self.min + self.max) * 0.5
+    }
+
+    // Distance along the ray at which it enters the box, if it does so before max_dist
+    pub fn ray_intersect(
+        &self,
+        ray_origin: Vec3<f64>,
+        inv_ray_dir: Vec3<f64>,
+        max_dist: f64,
+    ) -> Option<f64> {
+        let mut t_enter = 0.;
+        let mut t_exit = max_dist;
+
+        for axis in 0..3 {
+            // Slab method: clip the ray against a pair of parallel planes per axis
+            let t0 = (self.min[axis] - ray_origin[axis]) * inv_ray_dir[axis];
+            let t1 = (self.max[axis] - ray_origin[axis]) * inv_ray_dir[axis];
+            t_enter = t0.min(t1).max(t_enter);
+            t_exit = t0.max(t1).min(t_exit);
+        }
+
+        if t_enter <= t_exit {
+            Some(t_enter)
+        } else {
+            None
+        }
+    }
+
+    fn largest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent[0] >= extent[1] && extent[0] >= extent[2] {
+            0
+        } else if extent[1] >= extent[2] {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum NodeKind {
+    Leaf { first: usize, count: usize },
+    Interior { left: usize, right: usize },
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+pub struct Bvh {
+    nodes: Vec<Node>,
+    object_indices: Vec<usize>, // Leaves refer to contiguous ranges of this list
+}
+
+impl Bvh {
+    pub fn build(objects: &[Box<dyn RayIntersect>]) -> Self {
+        let bounds: Vec<Aabb> = objects.iter().map(|i| i.bounding_box()).collect();
+        let mut bvh = Self {
+            nodes: Vec::new(),
+            object_indices: (0..objects.len()).collect(),
+        };
+
+        if !objects.is_empty() {
+            bvh.build_node(&bounds, 0, objects.len());
+        }
+
+        bvh
+    }
+
+    fn build_node(&mut self, bounds: &[Aabb], first: usize, count: usize) -> usize {
+        let indices = &mut self.object_indices[first..first + count];
+        let node_bounds = indices
+            .iter()
+            .fold(Aabb::empty(), |acc, &i| acc.union(&bounds[i]));
+
+        let node_index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds: node_bounds,
+            kind: NodeKind::Leaf { first, count },
+        });
+
+        if count <= MAX_LEAF_SIZE {
+            return node_index;
+        }
+
+        // Median split along the axis where object centers are spread the most
+        let centroid_bounds = indices.iter().fold(Aabb::empty(), |acc, &i| {
+            let centroid = bounds[i].centroid();
+            acc.union(&Aabb::new(centroid, centroid))
+        });
+        let axis = centroid_bounds.largest_axis();
+        indices.sort_unstable_by(|&a, &b| {
+            bounds[a].centroid()[axis].total_cmp(&bounds[b].centroid()[axis])
+        });
+
+        let left_count = count / 2;
+        let left = self.build_node(bounds, first, left_count);
+        let right = self.build_node(bounds, first + left_count, count - left_count);
+        self.nodes[node_index].kind = NodeKind::Interior { left, right };
+
+        node_index
+    }
+
+    // Closest intersection as (object index, intersection)
+    pub fn closest_hit(
+        &self,
+        objects: &[Box<dyn RayIntersect>],
+        ray_origin: Vec3<f64>,
+        ray_dir: Vec3<f64>,
+    ) -> Option<(usize, Intersection)> {
+        let mut closest: Option<(usize, Intersection)> = None;
+        let mut closest_dist = f64::INFINITY;
+
+        self.traverse(ray_origin, ray_dir, |first, count, max_dist| {
+            for &i in &self.object_indices[first..first + count] {
+                if let Some(intersection) = objects[i].ray_intersect(ray_origin, ray_dir) {
+                    if intersection.dist < closest_dist {
+                        closest_dist = intersection.dist;
+                        closest = Some((i, intersection));
+                    }
+                }
+            }
+            *max_dist = closest_dist;
+            false
+        });
+
+        closest
+    }
+
+    // Whether anything is hit closer than max_dist, stopping at the first such object
+    pub fn any_hit(
+        &self,
+        objects: &[Box<dyn RayIntersect>],
+        ray_origin: Vec3<f64>,
+        ray_dir: Vec3<f64>,
+        max_dist: f64,
+    ) -> bool {
+        let mut hit = false;
+
+        self.traverse(ray_origin, ray_dir, |first, count, current_max_dist| {
+            *current_max_dist = max_dist;
+            hit = self.object_indices[first..first + count]
+                .iter()
+                .filter_map(|&i| objects[i].ray_intersect(ray_origin, ray_dir))
+                .any(|intersection| intersection.dist < max_dist);
+            hit
+        });
+
+        hit
+    }
+
+    // Visits leaves whose bounds the ray enters before the current max distance. The visitor
+    // receives a leaf range, may shrink the max distance and returns true to stop the traversal
+    fn traverse<F>(&self, ray_origin: Vec3<f64>, ray_dir: Vec3<f64>, mut visit_leaf: F)
+    where
+        F: FnMut(usize, usize, &mut f64) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_ray_dir = Vec3::new(1. / ray_dir[0], 1. / ray_dir[1], 1. / ray_dir[2]);
+        let mut max_dist = f64::INFINITY;
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node
+                .bounds
+                .ray_intersect(ray_origin, inv_ray_dir, max_dist)
+                .is_none()
+            {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { first, count } => {
+                    if visit_leaf(first, count, &mut max_dist) {
+                        return;
+                    }
+                }
+                NodeKind::Interior { left, right } => {
+                    // Visit the nearer child first so that far subtrees get culled more often
+                    let left_dist =
+                        self.nodes[left]
+                            .bounds
+                            .ray_intersect(ray_origin, inv_ray_dir, max_dist);
+                    let right_dist =
+                        self.nodes[right]
+                            .bounds
+                            .ray_intersect(ray_origin, inv_ray_dir, max_dist);
+                    match (left_dist, right_dist) {
+                        (Some(l), Some(r)) if l < r => stack.extend([right, left]),
+                        (Some(_), Some(_)) => stack.extend([left, right]),
+                        (Some(_), None) => stack.push(left),
+                        (None, Some(_)) => stack.push(right),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+// Pixel reconstruction filters. Samples are spread over the filter footprint around the pixel
+// center and weighted by the filter value at their offset
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+}
+
+impl Filter {
+    // Half of the footprint side, in pixels
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.,
+        }
+    }
+
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy) // All of them are separable
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.;
+        }
+
+        match self {
+            Filter::Box => 1.,
+            Filter::Tent => 1. - x,
+            Filter::Gaussian => {
+                // Shifted down so that it reaches zero at the radius instead of being cut off
+                let sigma: f64 = 0.5;
+                let gaussian = |x: f64| (-x * x / (2. * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(self.radius())) / (sigma * (2. * PI).sqrt())
+            }
+            Filter::Mitchell => {
+                // Mitchell–Netravali with B = C = 1/3
+                let (b, c) = (1. / 3., 1. / 3.);
+                if x < 1. {
+                    ((12. - 9. * b - 6. * c) * x * x * x
+                        + (-18. + 12. * b + 6. * c) * x * x
+                        + (6. - 2. * b))
+                        / 6.
+                } else {
+                    ((-b - 6. * c) * x * x * x
+                        + (6. * b + 30. * c) * x * x
+                        + (-12. * b - 48. * c) * x
+                        + (8. * b + 24. * c))
+                        / 6.
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "box" => Ok(Filter::Box),
+            "tent" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            _ => Err(format!(
+                "unknown filter `{name}`, expected box, tent, gaussian or mitchell"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Filter::Box => "box",
+            Filter::Tent => "tent",
+            Filter::Gaussian => "gaussian",
+            Filter::Mitchell => "mitchell",
+        })
+    }
+}
@@ -0,0 +1,98 @@
+use crate::integrator::Integrator;
+use crate::sampling::Rng;
+use crate::scene::Scene;
+use crate::vector::Vec3;
+use crate::{ambient_occlusion, intersect_objects, RenderSettings};
+
+// World space normal of the first hit, mapped from [-1, 1] to [0, 1] per axis
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        _settings: &RenderSettings,
+        _rng: &mut Rng,
+    ) -> Vec3<f64> {
+        match intersect_objects(origin, dir, scene) {
+            Some((_, props)) => (props.norm_dir + Vec3::new(1., 1., 1.)) * 0.5,
+            None => Vec3::new(0., 0., 0.),
+        }
+    }
+}
+
+// Distance to the first hit in scene units, 0 where nothing is hit. Best saved to a floating
+// point format
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Depth;
+
+impl Integrator for Depth {
+    fn radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        _settings: &RenderSettings,
+        _rng: &mut Rng,
+    ) -> Vec3<f64> {
+        let depth = intersect_objects(origin, dir, scene).map_or(0., |(_, props)| props.ray_length);
+        Vec3::new(depth, depth, depth)
+    }
+}
+
+// Ambient occlusion of the first hit, white where nothing is hit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+    samples: usize, // Per camera ray
+    max_distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(samples: usize, max_distance: f64) -> Self {
+        assert!(samples > 0, "ambient occlusion needs at least one sample");
+
+        Self {
+            samples,
+            max_distance,
+        }
+    }
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self::new(16, f64::INFINITY)
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        settings: &RenderSettings,
+        rng: &mut Rng,
+    ) -> Vec3<f64> {
+        let Some((_, props)) = intersect_objects(origin, dir, scene) else {
+            return Vec3::new(1., 1., 1.);
+        };
+
+        let norm_dir = if dir * props.norm_dir < 0. {
+            props.norm_dir
+        } else {
+            -props.norm_dir
+        };
+        let visible = ambient_occlusion(
+            props.hit_point + norm_dir * settings.ray_offset,
+            norm_dir,
+            self.samples,
+            self.max_distance,
+            scene,
+            rng,
+        );
+        Vec3::new(visible, visible, visible)
+    }
+}
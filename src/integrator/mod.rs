@@ -0,0 +1,122 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::sampling::Rng;
+use crate::scene::Scene;
+use crate::vector::Vec3;
+use crate::RenderSettings;
+
+pub mod debug;
+pub mod path;
+pub mod whitted;
+
+// Radiance told apart by whether it reached the camera from the first hit or a light seen
+// directly, or after bouncing around the scene
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SplitRadiance {
+    pub direct: Vec3<f64>,
+    pub indirect: Vec3<f64>,
+}
+
+impl SplitRadiance {
+    pub fn direct(direct: Vec3<f64>) -> Self {
+        Self {
+            direct,
+            indirect: Vec3::new(0., 0., 0.),
+        }
+    }
+
+    pub fn total(&self) -> Vec3<f64> {
+        self.direct + self.indirect
+    }
+}
+
+// Shading algorithm that estimates the light arriving along camera rays
+pub trait Integrator: Send + Sync {
+    // Radiance arriving at origin from the direction dir points to
+    fn radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        settings: &RenderSettings,
+        rng: &mut Rng,
+    ) -> Vec3<f64>;
+
+    // Same radiance split in its direct and indirect parts, all of it is direct by default
+    fn split_radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        settings: &RenderSettings,
+        rng: &mut Rng,
+    ) -> SplitRadiance {
+        SplitRadiance::direct(self.radiance(origin, dir, scene, settings, rng))
+    }
+}
+
+// The integrators that can be picked by name, for example on the command line
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IntegratorKind {
+    Whitted,
+    Path,
+    Normals,
+    Depth,
+    AmbientOcclusion,
+}
+
+impl IntegratorKind {
+    pub fn integrator(&self) -> Box<dyn Integrator> {
+        match self {
+            IntegratorKind::Whitted => Box::new(whitted::Whitted),
+            IntegratorKind::Path => Box::new(path::PathTracer),
+            IntegratorKind::Normals => Box::new(debug::Normals),
+            IntegratorKind::Depth => Box::new(debug::Depth),
+            IntegratorKind::AmbientOcclusion => Box::new(debug::AmbientOcclusion::default()),
+        }
+    }
+}
+
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "whitted" => Ok(IntegratorKind::Whitted),
+            "path" => Ok(IntegratorKind::Path),
+            "normals" => Ok(IntegratorKind::Normals),
+            "depth" => Ok(IntegratorKind::Depth),
+            "ao" => Ok(IntegratorKind::AmbientOcclusion),
+            _ => Err(format!(
+                "unknown integrator `{name}`, expected whitted, path, normals, depth or ao"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for IntegratorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IntegratorKind::Whitted => "whitted",
+            IntegratorKind::Path => "path",
+            IntegratorKind::Normals => "normals",
+            IntegratorKind::Depth => "depth",
+            IntegratorKind::AmbientOcclusion => "ao",
+        })
+    }
+}
+
+// Moves the origin of a secondary ray off the surface to the side the ray leaves towards
+fn offset_origin(
+    hit_point: Vec3<f64>,
+    norm_dir: Vec3<f64>,
+    dir: Vec3<f64>,
+    settings: &RenderSettings,
+) -> Vec3<f64> {
+    if dir * norm_dir > 0. {
+        hit_point + norm_dir * settings.ray_offset
+    } else {
+        hit_point - norm_dir * settings.ray_offset
+    }
+}
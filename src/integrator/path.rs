@@ -0,0 +1,187 @@
+use crate::integrator::{offset_origin, Integrator, SplitRadiance};
+use crate::light::{emitter_pdf, sample_emitter, LightSample};
+use crate::media::MediaStack;
+use crate::sampling::Rng;
+use crate::scene::Scene;
+use crate::vector::{Hadamard, Vec3};
+use crate::{intersect_objects, shading_normal, transmission, RenderSettings};
+
+// Paths always get this many bounces before Russian roulette may end them
+const ROULETTE_DEPTH: usize = 3;
+
+// Russian roulette keeps at least this chance of ending a path, so that it can't go on forever
+const MAX_SURVIVAL: f64 = 0.95;
+
+// Unbiased estimate of the radiance arriving along a ray. Lights are sampled directly at every
+// bounce, and light reached by the bounces themselves is weighted against those samples with
+// multiple importance sampling
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PathTracer;
+
+impl Integrator for PathTracer {
+    fn radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        settings: &RenderSettings,
+        rng: &mut Rng,
+    ) -> Vec3<f64> {
+        trace(origin, dir, scene, settings, rng).total()
+    }
+
+    fn split_radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        settings: &RenderSettings,
+        rng: &mut Rng,
+    ) -> SplitRadiance {
+        trace(origin, dir, scene, settings, rng)
+    }
+}
+
+fn trace(
+    mut origin: Vec3<f64>,
+    mut dir: Vec3<f64>,
+    scene: &Scene,
+    settings: &RenderSettings,
+    rng: &mut Rng,
+) -> SplitRadiance {
+    let mut media = MediaStack::new();
+    let mut radiance = SplitRadiance::direct(Vec3::new(0., 0., 0.));
+    let mut throughput = Vec3::new(1., 1., 1.);
+    // Density the last bounce was sampled with, None for camera rays and perfect mirrors which
+    // are the only way to see lights that can't be sampled directly
+    let mut bsdf_pdf: Option<f64> = None;
+
+    for depth in 0.. {
+        // Lights seen by the camera or lighting the first hit count as direct, unless they are
+        // only seen in a perfect mirror
+        let reaches_directly = depth == 0 || (depth == 1 && bsdf_pdf.is_some());
+        let mut add = |contribution: Vec3<f64>, direct: bool| {
+            if direct {
+                radiance.direct = radiance.direct + contribution;
+            } else {
+                radiance.indirect = radiance.indirect + contribution;
+            }
+        };
+
+        let hit = intersect_objects(origin, dir, scene);
+        let hit_dist = hit
+            .as_ref()
+            .map_or(f64::INFINITY, |(_, props)| props.ray_length);
+
+        for light in &scene.lights {
+            if let Some(sample) = light.hit(origin, dir).filter(|s| s.dist <= hit_dist) {
+                let weight = match (bsdf_pdf, sample.pdf) {
+                    (Some(bsdf_pdf), Some(light_pdf)) => power_heuristic(bsdf_pdf, light_pdf),
+                    _ => 1.,
+                };
+                add(
+                    throughput.hadamard(sample.radiance) * weight,
+                    reaches_directly,
+                );
+            }
+        }
+
+        let Some((obj, props)) = hit else {
+            add(
+                throughput.hadamard(settings.background.color(scene, dir)),
+                reaches_directly,
+            );
+            break;
+        };
+
+        let material = &scene.material_at(props.object_id, props.uv, props.hit_point);
+        // Emissive objects are sampled like area lights, so the same weighting applies
+        let emission = material.emission();
+        if emission != Vec3::new(0., 0., 0.) {
+            let weight = match bsdf_pdf {
+                Some(bsdf_pdf) => power_heuristic(
+                    bsdf_pdf,
+                    emitter_pdf(obj, dir, props.ray_length, props.norm_dir),
+                ),
+                None => 1.,
+            };
+            add(throughput.hadamard(emission) * weight, reaches_directly);
+        }
+
+        let norm_dir = shading_normal(material, &props, scene);
+        let interface = media.interface(
+            obj,
+            scene.volume_id(props.object_id),
+            dir * props.norm_dir < 0.,
+        );
+        let bsdf = material.bsdf(dir, norm_dir, &interface);
+
+        // Next event estimation
+        if bsdf.is_glossy() {
+            let point = props.hit_point + bsdf.norm_dir() * settings.ray_offset;
+            let mut next_event = |sample: LightSample| {
+                let cos = sample.dir * bsdf.norm_dir();
+                if cos <= 0. {
+                    return;
+                }
+
+                let weight = match sample.pdf {
+                    Some(light_pdf) => power_heuristic(light_pdf, bsdf.pdf(sample.dir)) / light_pdf,
+                    None => 1.,
+                };
+                let contribution = bsdf.eval(sample.dir).hadamard(sample.radiance) * (cos * weight);
+                if contribution == Vec3::new(0., 0., 0.) {
+                    return;
+                }
+
+                let transmission =
+                    transmission(point, sample.dir, sample.dist, scene, settings.ray_offset);
+                add(
+                    throughput.hadamard(contribution.hadamard(transmission)),
+                    depth == 0,
+                );
+            };
+
+            for light in &scene.lights {
+                if let Some(sample) = light.sample(point, rng) {
+                    next_event(sample);
+                }
+            }
+            for &i in scene.emitters() {
+                if let Some(sample) = sample_emitter(scene, i, point, settings.ray_offset, rng) {
+                    next_event(sample);
+                }
+            }
+        }
+
+        let Some(sample) = bsdf.sample(rng) else {
+            break;
+        };
+        throughput = throughput.hadamard(sample.weight);
+        bsdf_pdf = sample.pdf;
+        dir = sample.dir;
+        if sample.refracted {
+            media = media.refracted(&interface, obj);
+        }
+        origin = offset_origin(props.hit_point, props.norm_dir, dir, settings);
+
+        if depth >= ROULETTE_DEPTH {
+            let survival = throughput[0]
+                .max(throughput[1])
+                .max(throughput[2])
+                .min(MAX_SURVIVAL);
+            if rng.next_f64() >= survival {
+                break;
+            }
+            throughput = throughput * (1. / survival);
+        }
+    }
+
+    radiance
+}
+
+// Weight of a sample taken with density pdf when another strategy could have produced it with
+// density other_pdf
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    pdf * pdf / (pdf * pdf + other_pdf * other_pdf)
+}
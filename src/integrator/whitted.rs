@@ -0,0 +1,255 @@
+use crate::bsdf::Bsdf;
+use crate::integrator::{offset_origin, Integrator, SplitRadiance};
+use crate::light::{sample_emitter, LightSample};
+use crate::material::{Material, Phong};
+use crate::media::MediaStack;
+use crate::sampling::Rng;
+use crate::scene::Scene;
+use crate::vector::{Hadamard, Vec3};
+use crate::{intersect_objects, shading_normal, transmission, RayHitProperties, RenderSettings};
+
+// Emissive objects are sampled this many times at every hit
+const EMITTER_SAMPLES: usize = 16;
+
+// Rough reflections and refractions of the first hit are averaged over this many rays. Deeper
+// ones take a single ray each, which keeps the count from growing with every bounce while pixel
+// samples still average them out
+const GLOSSY_SAMPLES: usize = 16;
+
+// Direct lighting at every hit, with reflections and refractions traced recursively up to
+// RenderSettings::max_depth
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Whitted;
+
+impl Integrator for Whitted {
+    fn radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        settings: &RenderSettings,
+        rng: &mut Rng,
+    ) -> Vec3<f64> {
+        trace(origin, dir, scene, settings, &MediaStack::new(), rng, 0)
+    }
+
+    fn split_radiance(
+        &self,
+        origin: Vec3<f64>,
+        dir: Vec3<f64>,
+        scene: &Scene,
+        settings: &RenderSettings,
+        rng: &mut Rng,
+    ) -> SplitRadiance {
+        shade(origin, dir, scene, settings, &MediaStack::new(), rng, 0)
+    }
+}
+
+fn trace(
+    origin: Vec3<f64>,
+    dir: Vec3<f64>,
+    scene: &Scene,
+    settings: &RenderSettings,
+    media: &MediaStack,
+    rng: &mut Rng,
+    depth: usize,
+) -> Vec3<f64> {
+    shade(origin, dir, scene, settings, media, rng, depth).total()
+}
+
+// Lighting and emission of the hit as the direct part, reflections and refractions as the
+// indirect one
+fn shade(
+    origin: Vec3<f64>,
+    dir: Vec3<f64>,
+    scene: &Scene,
+    settings: &RenderSettings,
+    media: &MediaStack,
+    rng: &mut Rng,
+    depth: usize,
+) -> SplitRadiance {
+    if depth > settings.max_depth {
+        return SplitRadiance::direct(settings.background.color(scene, dir));
+    }
+
+    match intersect_objects(origin, dir, scene) {
+        None => SplitRadiance::direct(settings.background.color(scene, dir)),
+
+        Some((obj, props)) => {
+            let material = &scene.material_at(props.object_id, props.uv, props.hit_point);
+            // Lighting follows normal and bump maps, while rays leave from the actual surface
+            let norm_dir = shading_normal(material, &props, scene);
+
+            // Geometric normals point outwards, except for open surfaces which face the ray
+            let interface = media.interface(
+                obj,
+                scene.volume_id(props.object_id),
+                dir * props.norm_dir < 0.,
+            );
+            let bsdf = material.bsdf(dir, norm_dir, &interface);
+
+            let lighting = match material {
+                Material::Phong(phong) => {
+                    phong_lighting(phong, dir, norm_dir, &props, scene, settings, rng)
+                }
+                Material::Pbr(_) => bsdf_lighting(bsdf.as_ref(), &props, scene, settings, rng),
+            };
+            let direct = material.emission()
+                + lighting
+                + emitter_lighting(bsdf.as_ref(), &props, scene, settings, rng);
+            let mut indirect = Vec3::new(0., 0., 0.);
+
+            let rays = match material {
+                Material::Phong(phong) if phong.roughness > 0. && depth == 0 => GLOSSY_SAMPLES,
+                _ => 1,
+            };
+            for _ in 0..rays {
+                for sample in bsdf.specular_samples(rng) {
+                    let refracted_media;
+                    let media = if sample.refracted {
+                        refracted_media = media.refracted(&interface, obj);
+                        &refracted_media
+                    } else {
+                        media
+                    };
+                    indirect = indirect
+                        + trace(
+                            offset_origin(props.hit_point, props.norm_dir, sample.dir, settings),
+                            sample.dir,
+                            scene,
+                            settings,
+                            media,
+                            rng,
+                            depth + 1,
+                        )
+                        .hadamard(sample.weight);
+                }
+            }
+            indirect = indirect * (1. / rays as f64);
+
+            SplitRadiance { direct, indirect }
+        }
+    }
+}
+
+// The diffuse and specular terms of the Phong model, with each light's own intensities
+fn phong_lighting(
+    material: &Phong,
+    dir: Vec3<f64>,
+    norm_dir: Vec3<f64>,
+    props: &RayHitProperties,
+    scene: &Scene,
+    settings: &RenderSettings,
+    rng: &mut Rng,
+) -> Vec3<f64> {
+    // Each light contributes its own colored radiance
+    let mut diffuse_light = Vec3::new(0., 0., 0.);
+    let mut specular_light = Vec3::new(0., 0., 0.);
+    for i in &scene.lights {
+        let transmission = i.transmission(
+            props.hit_point + props.norm_dir * settings.ray_offset,
+            props.norm_dir,
+            scene,
+            settings.ray_offset,
+            rng,
+        );
+        if transmission == Vec3::new(0., 0., 0.) {
+            continue;
+        }
+
+        let light_color = i.get_light_color().hadamard(transmission);
+        diffuse_light =
+            diffuse_light + light_color * i.get_diffuse_light_intensity(props.hit_point, norm_dir);
+        specular_light = specular_light
+            + light_color
+                * i.get_specular_light_intensity(
+                    props.hit_point,
+                    norm_dir,
+                    material.specular_exponent,
+                    dir,
+                );
+    }
+
+    material.diffuse_color.hadamard(diffuse_light) * material.diffuse_albedo
+        + specular_light * material.specular_albedo
+}
+
+// One sample of every light through the BSDF, with ambient light reflected as a whole
+fn bsdf_lighting(
+    bsdf: &dyn Bsdf,
+    props: &RayHitProperties,
+    scene: &Scene,
+    settings: &RenderSettings,
+    rng: &mut Rng,
+) -> Vec3<f64> {
+    let point = props.hit_point + bsdf.norm_dir() * settings.ray_offset;
+    let mut lighting = Vec3::new(0., 0., 0.);
+    for i in &scene.lights {
+        if let Some(radiance) = i.ambient() {
+            let transmission =
+                i.transmission(point, bsdf.norm_dir(), scene, settings.ray_offset, rng);
+            lighting = lighting + bsdf.reflectance().hadamard(radiance.hadamard(transmission));
+            continue;
+        }
+
+        // Mirror rays never hit these lights, so all of eval applies
+        if let Some(sample) = i.sample(point, rng) {
+            lighting = lighting
+                + sample_lighting(bsdf.eval(sample.dir), bsdf, point, &sample, scene, settings);
+        }
+    }
+    lighting
+}
+
+// Emissive objects as area lights, each sampled at EMITTER_SAMPLES points. Reflection rays see
+// them too, so they only light the share of the BSDF that those rays don't trace
+fn emitter_lighting(
+    bsdf: &dyn Bsdf,
+    props: &RayHitProperties,
+    scene: &Scene,
+    settings: &RenderSettings,
+    rng: &mut Rng,
+) -> Vec3<f64> {
+    let mut lighting = Vec3::new(0., 0., 0.);
+    if !bsdf.is_glossy() {
+        return lighting;
+    }
+
+    let point = props.hit_point + bsdf.norm_dir() * settings.ray_offset;
+    for &i in scene.emitters() {
+        for _ in 0..EMITTER_SAMPLES {
+            if let Some(sample) = sample_emitter(scene, i, point, settings.ray_offset, rng) {
+                let eval = bsdf.eval_untraced(sample.dir);
+                lighting = lighting + sample_lighting(eval, bsdf, point, &sample, scene, settings);
+            }
+        }
+    }
+    lighting * (1. / EMITTER_SAMPLES as f64)
+}
+
+// Light from a single sample reflected back along the ray, with eval the BSDF towards it
+fn sample_lighting(
+    eval: Vec3<f64>,
+    bsdf: &dyn Bsdf,
+    point: Vec3<f64>,
+    sample: &LightSample,
+    scene: &Scene,
+    settings: &RenderSettings,
+) -> Vec3<f64> {
+    let cos = sample.dir * bsdf.norm_dir();
+    if cos <= 0. {
+        return Vec3::new(0., 0., 0.);
+    }
+    let contribution = eval.hadamard(sample.radiance) * (cos / sample.pdf.unwrap_or(1.));
+    if contribution == Vec3::new(0., 0., 0.) {
+        return contribution;
+    }
+
+    contribution.hadamard(transmission(
+        point,
+        sample.dir,
+        sample.dist,
+        scene,
+        settings.ray_offset,
+    ))
+}
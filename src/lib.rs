@@ -1,48 +1,265 @@
-use image::{ImageBuffer, RgbImage};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use image::{ImageBuffer, Rgb, Rgb32FImage};
 
 use scene::Scene;
 
+use crate::aov::Aov;
+use crate::filter::Filter;
+use crate::integrator::whitted::Whitted;
+use crate::integrator::{Integrator, SplitRadiance};
+use crate::material::Material;
 use crate::objects::RayIntersect;
-use crate::vector::{Normalize, Vec3};
+use crate::sampling::{cosine_hemisphere, orthonormal_basis, stratified, Rng};
+use crate::vector::{Cross, Hadamard, Norm, Normalize, Vec3};
 
+pub mod aov;
+pub mod bsdf;
+pub mod bvh;
+pub mod filter;
+pub mod integrator;
 pub mod light;
+pub mod loader;
 pub mod material;
+pub mod media;
+pub mod noise;
 pub mod objects;
+pub mod output;
+pub mod sampling;
 pub mod scene;
+pub mod texture;
 pub mod vector;
 
-const EPS: f64 = 1e-3;
-const MAX_DEPTH: usize = 4;
+const TILE_SIZE: usize = 32;
 
-pub fn render(scene: &Scene) -> RgbImage {
-    let mut result: RgbImage = ImageBuffer::new(scene.cam.width as u32, scene.cam.height as u32);
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Background {
+    Scene, // Scene::background_color
+    Color(Vec3<f64>),
+    Gradient {
+        horizon: Vec3<f64>,
+        zenith: Vec3<f64>,
+    },
+}
 
-    for j in 0..scene.cam.height {
-        for i in 0..scene.cam.width {
-            let x = (2. * ((i as f64) + 0.5) / (scene.cam.width as f64) - 1.)
-                * f64::tan(scene.cam.fov / 2.)
-                * (scene.cam.width as f64)
-                / (scene.cam.height as f64);
-            let y = -(2. * ((j as f64) + 0.5) / (scene.cam.height as f64) - 1.)
-                * f64::tan(scene.cam.fov / 2.);
+impl Background {
+    fn color(&self, scene: &Scene, dir: Vec3<f64>) -> Vec3<f64> {
+        match *self {
+            Background::Scene => scene.background_color,
+            Background::Color(color) => color,
+            Background::Gradient { horizon, zenith } => {
+                let t = dir[1].max(0.); // Sine of the elevation for a normalized direction
+                horizon * (1. - t) + zenith * t
+            }
+        }
+    }
+}
 
-            let ray_dir = (scene.cam.dir + Vec3::new(x, y, 0.)).normalize();
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub threads: usize,
+    pub samples: usize, // Stratified on a grid, see sampling::stratified
+    pub filter: Filter,
+    pub seed: u64,        // Sample jitter is fully determined by it
+    pub max_depth: usize, // Of the Whitted integrator, paths end by Russian roulette
+    pub ray_offset: f64,  // Secondary rays start this far from surfaces to avoid self-intersection
+    pub background: Background,
+}
 
-            result.put_pixel(
-                i as u32,
-                j as u32,
-                ray_trace(scene.cam.pos, ray_dir, scene, 0).into(),
-            );
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+            samples: 1,
+            filter: Filter::Box,
+            seed: 0,
+            max_depth: 4,
+            ray_offset: 1e-3,
+            background: Background::Scene,
+        }
+    }
+}
+
+pub fn render_with_defaults(scene: &Scene) -> Rgb32FImage {
+    render(scene, &Whitted, &RenderSettings::default())
+}
+
+pub fn render(
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+) -> Rgb32FImage {
+    render_with_aovs(scene, integrator, settings, &[]).0
+}
+
+// Renders the image along with the requested auxiliary buffers, in the same order
+pub fn render_with_aovs(
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    aovs: &[Aov],
+) -> (Rgb32FImage, Vec<Rgb32FImage>) {
+    let mut layers: Vec<Rgb32FImage> = (0..=aovs.len())
+        .map(|_| ImageBuffer::new(scene.cam.width as u32, scene.cam.height as u32))
+        .collect();
+
+    let tiles_x = scene.cam.width.div_ceil(TILE_SIZE);
+    let tiles_y = scene.cam.height.div_ceil(TILE_SIZE);
+    let next_tile = AtomicUsize::new(0);
+
+    // Every worker grabs the next untouched tile until none are left. Pixels don't depend on
+    // each other, so the image is the same regardless of the number of threads
+    let rendered_tiles: Vec<Vec<(Tile, Vec<PixelLayers>)>> = thread::scope(|s| {
+        let workers: Vec<_> = (0..settings.threads.max(1))
+            .map(|_| {
+                s.spawn(|| {
+                    let mut tiles = Vec::new();
+                    loop {
+                        let tile_index = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if tile_index >= tiles_x * tiles_y {
+                            break;
+                        }
+
+                        let tile = Tile::new(
+                            tile_index % tiles_x * TILE_SIZE,
+                            tile_index / tiles_x * TILE_SIZE,
+                            scene,
+                        );
+                        tiles.push((tile, render_tile(scene, integrator, settings, aovs, &tile)));
+                    }
+                    tiles
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("Render worker panicked"))
+            .collect()
+    });
+
+    for (tile, pixels) in rendered_tiles.into_iter().flatten() {
+        for (k, pixel_layers) in pixels.into_iter().enumerate() {
+            for (layer, pixel) in layers.iter_mut().zip(pixel_layers) {
+                layer.put_pixel(
+                    (tile.x + k % tile.width) as u32,
+                    (tile.y + k / tile.width) as u32,
+                    pixel,
+                );
+            }
+        }
+    }
+
+    let image = layers.remove(0);
+    (image, layers)
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Tile {
+    fn new(x: usize, y: usize, scene: &Scene) -> Self {
+        Self {
+            x,
+            y,
+            width: TILE_SIZE.min(scene.cam.width - x),
+            height: TILE_SIZE.min(scene.cam.height - y),
+        }
+    }
+}
+
+// The image followed by the AOVs
+type PixelLayers = Vec<Rgb<f32>>;
+
+fn render_tile(
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    aovs: &[Aov],
+    tile: &Tile,
+) -> Vec<PixelLayers> {
+    let mut pixels = Vec::with_capacity(tile.width * tile.height);
+
+    for j in tile.y..tile.y + tile.height {
+        for i in tile.x..tile.x + tile.width {
+            let radiance = render_pixel(scene, integrator, settings, i, j);
+
+            let mut layers = vec![radiance.total()];
+            if !aovs.is_empty() {
+                let ray_dir = scene.cam.ray_dir(i as f64 + 0.5, j as f64 + 0.5);
+                let hit = intersect_objects(scene.cam.pos, ray_dir, scene);
+                let hit = hit.as_ref().map(|(_, props)| props);
+                layers.extend(aovs.iter().map(|aov| aov.value(scene, hit, &radiance)));
+            }
+            pixels.push(layers.into_iter().map(Rgb::from).collect());
+        }
+    }
+
+    pixels
+}
+
+fn render_pixel(
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    settings: &RenderSettings,
+    i: usize,
+    j: usize,
+) -> SplitRadiance {
+    let center_x = i as f64 + 0.5;
+    let center_y = j as f64 + 0.5;
+    let mut rng = Rng::for_pixel(settings.seed, i, j);
+
+    if settings.samples <= 1 {
+        // A single sample can't reconstruct anything, so it just goes through the center
+        let ray_dir = scene.cam.ray_dir(center_x, center_y);
+        return integrator.split_radiance(scene.cam.pos, ray_dir, scene, settings, &mut rng);
+    }
+
+    // Stratified sampling: one randomly placed sample per cell of a grid laid over the filter
+    let radius = settings.filter.radius();
+
+    let mut direct = Vec3::new(0., 0., 0.);
+    let mut indirect = Vec3::new(0., 0., 0.);
+    let mut total_weight = 0.;
+    for (u, v) in stratified(settings.samples, &mut rng) {
+        let dx = (2. * u - 1.) * radius;
+        let dy = (2. * v - 1.) * radius;
+        let weight = settings.filter.weight(dx, dy);
+        if weight == 0. {
+            continue;
         }
+
+        let ray_dir = scene.cam.ray_dir(center_x + dx, center_y + dy);
+        let radiance = integrator.split_radiance(scene.cam.pos, ray_dir, scene, settings, &mut rng);
+        direct = direct + radiance.direct * weight;
+        indirect = indirect + radiance.indirect * weight;
+        total_weight += weight;
     }
 
-    result
+    if total_weight.abs() < f64::EPSILON {
+        SplitRadiance::direct(Vec3::new(0., 0., 0.))
+    } else {
+        SplitRadiance {
+            direct: direct * (1. / total_weight),
+            indirect: indirect * (1. / total_weight),
+        }
+    }
 }
 
 struct RayHitProperties {
     ray_length: f64,
     hit_point: Vec3<f64>,
     norm_dir: Vec3<f64>,
+    uv: (f64, f64),
+    tangent: Vec3<f64>,
+    bitangent: Vec3<f64>,
+    object_id: usize, // Index into Scene::objects
 }
 
 fn intersect_objects(
@@ -50,93 +267,243 @@ fn intersect_objects(
     dir: Vec3<f64>,
     scene: &Scene,
 ) -> Option<(&dyn RayIntersect, RayHitProperties)> {
-    let mut closest_intersection = f64::INFINITY;
-    let mut closest_intersection_object = None;
-    let mut closest_intersection_hit_point = Vec3::new(0., 0., 0.);
-    let mut closest_intersection_norm_dir = Vec3::new(0., 0., 0.);
+    scene
+        .bvh()
+        .closest_hit(scene.objects(), origin, dir)
+        .map(|(i, intersection)| {
+            (
+                scene.objects()[i].as_ref(),
+                RayHitProperties {
+                    ray_length: intersection.dist,
+                    hit_point: intersection.hit_point,
+                    norm_dir: intersection.norm_dir,
+                    uv: intersection.uv,
+                    tangent: intersection.tangent,
+                    bitangent: intersection.bitangent,
+                    object_id: i,
+                },
+            )
+        })
+}
 
-    for i in &scene.objects {
-        if let Some((intersection, hit_point, norm_dir)) = i.ray_intersect(origin, dir) {
-            if intersection < closest_intersection {
-                closest_intersection = intersection;
-                closest_intersection_object = Some(i);
-                closest_intersection_hit_point = hit_point;
-                closest_intersection_norm_dir = norm_dir;
-            }
-        }
-    }
+// UV step of the finite differences taken on height maps
+const BUMP_DELTA: f64 = 1e-3;
 
-    closest_intersection_object.map(|obj| {
-        (
-            obj.as_ref(),
-            RayHitProperties {
-                ray_length: closest_intersection,
-                hit_point: closest_intersection_hit_point,
-                norm_dir: closest_intersection_norm_dir,
-            },
-        )
-    })
-}
+// The normal to shade a hit with, which normal and height maps of the material tilt away from
+// the geometric one without ever crossing the surface
+fn shading_normal(material: &Material, props: &RayHitProperties, scene: &Scene) -> Vec3<f64> {
+    let norm_dir = props.norm_dir;
+    let (u, v) = props.uv;
 
-fn ray_trace(origin: Vec3<f64>, dir: Vec3<f64>, scene: &Scene, depth: usize) -> Vec3<f64> {
-    if depth > MAX_DEPTH {
-        return scene.background_color;
-    }
+    let perturbed = if let Some(texture) = material.normal_map() {
+        // The tangent frame follows u and v, falling back to any frame where they degenerate
+        let tangent = props.tangent - norm_dir * (props.tangent * norm_dir);
+        let (tangent, bitangent) = if tangent.norm() > 1e-12 {
+            let tangent = tangent.normalize();
+            let bitangent = norm_dir.cross(tangent);
+            if bitangent * props.bitangent < 0. {
+                (tangent, -bitangent)
+            } else {
+                (tangent, bitangent)
+            }
+        } else {
+            orthonormal_basis(norm_dir)
+        };
 
-    match intersect_objects(origin, dir, scene) {
-        None => scene.background_color,
+        let color = scene.texture(texture).color(props.uv, props.hit_point);
+        tangent * (2. * color[0] - 1.)
+            + bitangent * (2. * color[1] - 1.)
+            + norm_dir * (2. * color[2] - 1.)
+    } else if let Some((texture, scale)) = material.height_map() {
+        // Raise the hit point along the normal and see how that bends the surface around it
+        let texture = scene.texture(texture);
+        let height = |uv, point| texture.value(uv, point) * scale;
+        let base = height(props.uv, props.hit_point);
+        let slope_u = (height(
+            (u + BUMP_DELTA, v),
+            props.hit_point + props.tangent * BUMP_DELTA,
+        ) - base)
+            / BUMP_DELTA;
+        let slope_v = (height(
+            (u, v + BUMP_DELTA),
+            props.hit_point + props.bitangent * BUMP_DELTA,
+        ) - base)
+            / BUMP_DELTA;
 
-        Some((obj, props)) => {
-            let mut diffuse_light_intensity = 0.;
-            let mut specular_light_intensity = 0.;
-            let mut total_light_color = Vec3::new(0., 0., 0.);
-            for i in &scene.lights {
-                if i.is_in_shadow(props.hit_point + props.norm_dir * EPS, scene) {
-                    continue;
-                }
+        (props.tangent + norm_dir * slope_u).cross(props.bitangent + norm_dir * slope_v)
+    } else {
+        return norm_dir;
+    };
 
-                diffuse_light_intensity +=
-                    i.get_diffuse_light_intensity(props.hit_point, props.norm_dir);
-                specular_light_intensity +=
-                    i.get_specular_light_intensity(props.hit_point, props.norm_dir, obj, dir);
-                total_light_color = total_light_color + i.get_light_color();
-            }
+    // Mirrored or flipped mappings give frames of the other handedness
+    let perturbed = if perturbed * norm_dir < 0. {
+        -perturbed
+    } else {
+        perturbed
+    };
+    if perturbed.norm() > 1e-12 {
+        perturbed.normalize()
+    } else {
+        norm_dir
+    }
+}
 
-            total_light_color = total_light_color * (1. / scene.lights.len() as f64);
+// Fraction of the hemisphere around norm_dir that is free of objects up to max_distance away,
+// weighted by cosine like the light a diffuse surface receives from it
+fn ambient_occlusion(
+    point: Vec3<f64>,
+    norm_dir: Vec3<f64>,
+    samples: usize,
+    max_distance: f64,
+    scene: &Scene,
+    rng: &mut Rng,
+) -> f64 {
+    let (tangent, bitangent) = orthonormal_basis(norm_dir);
+    let samples = stratified(samples, rng);
+    let free = samples
+        .iter()
+        .filter(|&&(u, v)| {
+            let local = cosine_hemisphere(u, v);
+            let dir = tangent * local[0] + bitangent * local[1] + norm_dir * local[2];
+            !scene
+                .bvh()
+                .any_hit(scene.objects(), point, dir, max_distance)
+        })
+        .count();
 
-            let reflect_color = ray_trace(
-                props.hit_point + props.norm_dir * EPS,
-                objects::reflect(dir, props.norm_dir).normalize(),
-                scene,
-                depth + 1,
-            );
-            let refract_dir =
-                objects::refract(dir, props.norm_dir, obj.get_material().refractive_index)
-                    .normalize();
-            let refract_color = ray_trace(
-                if refract_dir * props.norm_dir > 0. {
-                    props.hit_point + props.norm_dir * EPS
-                } else {
-                    props.hit_point - props.norm_dir * EPS
-                },
-                refract_dir,
-                scene,
-                depth + 1,
-            );
+    free as f64 / samples.len() as f64
+}
+
+// Light carried along a shadow ray over max_dist. Opaque objects block it, while objects that
+// refract let it through tinted by their color once per surface crossed. The ray continues
+// ray_offset past each of them before looking for the next one
+fn transmission(
+    mut origin: Vec3<f64>,
+    dir: Vec3<f64>,
+    mut max_dist: f64,
+    scene: &Scene,
+    ray_offset: f64,
+) -> Vec3<f64> {
+    let mut transmission = Vec3::new(1., 1., 1.);
+    while let Some((i, hit)) = scene.bvh().closest_hit(scene.objects(), origin, dir) {
+        if hit.dist >= max_dist {
+            break;
+        }
 
-            obj.get_material().diffuse_color
-                * diffuse_light_intensity
-                * obj.get_material().diffuse_albedo
-                + total_light_color * specular_light_intensity * obj.get_material().specular_albedo
-                + reflect_color * obj.get_material().reflective_albedo
-                + refract_color * obj.get_material().refractive_albedo
+        let transmittance = scene.material_at(i, hit.uv, hit.hit_point).transmittance();
+        if transmittance == Vec3::new(0., 0., 0.) {
+            return Vec3::new(0., 0., 0.);
         }
+        transmission = transmission.hadamard(transmittance);
+
+        origin = hit.hit_point + dir * ray_offset;
+        max_dist -= hit.dist + ray_offset;
     }
+
+    transmission
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::aov::Aov;
+    use crate::bvh::Bvh;
+    use crate::filter::Filter;
+    use std::collections::HashMap;
+    use std::f64::consts::PI;
+    use std::path::Path;
+
+    use crate::integrator::debug::{AmbientOcclusion, Depth, Normals};
+    use crate::integrator::path::PathTracer;
+    use crate::integrator::whitted::Whitted;
+    use crate::integrator::Integrator;
+    use crate::light::{
+        Ambient, Attenuation, Directional, Disk, LightIntensity, Point, Rectangle, Spot,
+    };
+    use crate::loader::{load_scene, SceneError};
+    use crate::material::{Fresnel, Material, MaterialTextures, Pbr, Phong};
+    use crate::media::MediaStack;
+    use crate::noise::Perlin;
+    use crate::objects::circular_plane::CircularPlane;
+    use crate::objects::mesh::Mesh;
+    use crate::objects::sphere::Sphere;
+    use crate::objects::triangle::Triangle;
+    use crate::objects::{jitter, reflect, refract, RayIntersect};
+    use crate::output::{self, Encoding, ToneMapOperator, ToneMapping};
+    use crate::sampling::{stratified, uniform_sphere, Rng};
+    use crate::scene::{Cam, Scene};
+    use crate::texture::{
+        ImageTexture, Interpolation, Mapping, Pattern, Procedural, Texture, TextureId, Wrap,
+    };
     use crate::vector::{Norm, Normalize, Vec3};
+    use crate::{
+        ambient_occlusion, intersect_objects, render, render_with_aovs, render_with_defaults,
+        shading_normal, Background, RenderSettings,
+    };
+    use image::{ImageBuffer, Rgb, Rgb32FImage, RgbImage};
+
+    fn glass() -> Phong {
+        Phong {
+            diffuse_color: Vec3::new(0.6, 0.7, 0.8),
+            diffuse_albedo: 0.1,
+            specular_exponent: 125.,
+            specular_albedo: 0.5,
+            reflective_albedo: 0.1,
+            refractive_index: 1.5,
+            refractive_albedo: 0.8,
+            fresnel: None,
+            roughness: 0.,
+            emission: Vec3::new(0., 0., 0.),
+            emission_strength: 1.,
+            textures: MaterialTextures::default(),
+        }
+    }
+
+    fn matte(albedo: f64) -> Phong {
+        Phong {
+            diffuse_color: Vec3::new(1., 1., 1.),
+            diffuse_albedo: albedo,
+            specular_exponent: 1.,
+            specular_albedo: 0.,
+            reflective_albedo: 0.,
+            refractive_index: 1.,
+            refractive_albedo: 0.,
+            fresnel: None,
+            roughness: 0.,
+            emission: Vec3::new(0., 0., 0.),
+            emission_strength: 1.,
+            textures: MaterialTextures::default(),
+        }
+    }
+
+    fn phong(material: &Material) -> Phong {
+        match material {
+            Material::Phong(phong) => *phong,
+            Material::Pbr(_) => panic!("Expected a Phong material"),
+        }
+    }
+
+    fn test_scene(width: usize, height: usize) -> Scene {
+        Scene::new(
+            Cam::look_at(
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 0., -1.),
+                Vec3::new(0., 1., 0.),
+                f64::to_radians(60.),
+                width,
+                height,
+            ),
+            Vec3::new(0.2, 0.7, 0.8),
+            vec![
+                Box::new(Sphere::new(Vec3::new(-1., 0., -8.), 2., glass().into())),
+                Box::new(Sphere::new(Vec3::new(2., 1., -12.), 3., glass().into())),
+            ],
+            vec![Box::new(Point::new(
+                Vec3::new(-20., 20., 20.),
+                1.5,
+                Vec3::new(1., 1., 1.),
+            ))],
+        )
+    }
 
     #[test]
     fn test_vec3_add() {
@@ -193,4 +560,1600 @@ mod tests {
         assert_ne!(1.0, vec1.norm());
         assert_eq!(1.0, vec1.normalize().norm());
     }
+
+    #[test]
+    fn test_cam_look_at() {
+        let cam = Cam::look_at(
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            f64::to_radians(60.),
+            64,
+            48,
+        );
+        let center = cam.ray_dir(32.0, 24.0);
+        assert!((center - Vec3::new(-1.0, 0.0, 0.0)).norm() < 1e-9);
+
+        // Top left corner must lean up and to the camera's left (+Z when looking along -X)
+        let corner = cam.ray_dir(0.0, 0.0);
+        assert!(corner[1] > 0.0);
+        assert!(corner[2] > 0.0);
+
+        // Looking straight down leaves no way to tell up, which can't be turned into a basis
+        let looking_down = std::panic::catch_unwind(|| {
+            Cam::look_at(
+                Vec3::new(0.0, 10.0, 0.0),
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                f64::to_radians(60.),
+                64,
+                48,
+            )
+        });
+        assert!(looking_down.is_err());
+    }
+
+    #[test]
+    fn test_render_settings() {
+        let one_pixel = |pos: Vec3<f64>, target: Vec3<f64>, up: Vec3<f64>| {
+            Cam::look_at(pos, target, up, f64::to_radians(1.), 1, 1)
+        };
+        let pixel = |scene: &Scene, settings: &RenderSettings| {
+            let rgb = render(scene, &Whitted, settings).get_pixel(0, 0).0;
+            Vec3::new(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64)
+        };
+
+        // Looking straight up sees the zenith color and looking down the horizon color
+        let horizon = Vec3::new(0.8, 0.9, 1.);
+        let zenith = Vec3::new(0.1, 0.3, 0.7);
+        let settings = RenderSettings {
+            background: Background::Gradient { horizon, zenith },
+            ..RenderSettings::default()
+        };
+        for (target, up, expected) in [
+            (Vec3::new(0., 1., 0.), Vec3::new(0., 0., -1.), zenith),
+            (Vec3::new(0., -1., 0.), Vec3::new(0., 0., -1.), horizon),
+        ] {
+            let origin = Vec3::new(0., 0., 0.);
+            let scene = Scene::new(one_pixel(origin, target, up), origin, vec![], vec![]);
+            assert!((pixel(&scene, &settings) - expected).norm() < 1e-4);
+        }
+
+        // Half mirrored walls facing each other, the light seen through the second reflection is
+        // cut off by the depth limit
+        let mut mirror = matte(0.5);
+        mirror.reflective_albedo = 0.5;
+        let wall = |x: f64| -> Box<dyn RayIntersect> {
+            Box::new(CircularPlane::new(
+                Vec3::new(x, 0., 0.),
+                100.,
+                mirror.into(),
+                Vec3::new(0., 1., 0.),
+                Vec3::new(0., 0., 1.),
+            ))
+        };
+        let corridor = Scene::new(
+            one_pixel(
+                Vec3::new(0., 0., 0.),
+                Vec3::new(1., 0.1, -1.),
+                Vec3::new(0., 1., 0.),
+            ),
+            Vec3::new(0., 0., 0.),
+            vec![wall(-1.), wall(1.)],
+            vec![Box::new(Point::new(
+                Vec3::new(0., 0.3, -3.),
+                1.,
+                Vec3::new(1., 1., 1.),
+            ))],
+        );
+        let shallow = pixel(
+            &corridor,
+            &RenderSettings {
+                max_depth: 0,
+                ..RenderSettings::default()
+            },
+        );
+        let deep = pixel(
+            &corridor,
+            &RenderSettings {
+                max_depth: 2,
+                ..RenderSettings::default()
+            },
+        );
+        assert!(deep[0] > shallow[0] + 1e-3);
+
+        // A lit sphere a trillion units across shadows itself where the default offset is lost
+        // in the rounding of its hit points, scaling the offset with the scene removes the acne
+        let scale = 1e12;
+        let scene = Scene::new(
+            Cam::look_at(
+                Vec3::new(0., 3., 10.) * scale,
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                f64::to_radians(30.),
+                16,
+                12,
+            ),
+            Vec3::new(0., 0., 0.),
+            vec![Box::new(Sphere::new(
+                Vec3::new(0., 0., 0.),
+                2. * scale,
+                matte(1.).into(),
+            ))],
+            vec![Box::new(Point::new(
+                Vec3::new(0., 30., 30.) * scale,
+                1.,
+                Vec3::new(1., 1., 1.),
+            ))],
+        );
+        let black_pixels = |ray_offset: f64| {
+            let settings = RenderSettings {
+                ray_offset,
+                ..RenderSettings::default()
+            };
+            let image = render(&scene, &Whitted, &settings);
+            image.pixels().filter(|p| p[0] == 0.).count()
+        };
+        let default_offset = RenderSettings::default().ray_offset;
+        let unlit = black_pixels(default_offset * scale);
+        assert!(black_pixels(default_offset) > unlit);
+        // The same count of pixels miss the sphere or face away from the light at any scale
+        let unscaled = Scene::new(
+            Cam::look_at(
+                Vec3::new(0., 3., 10.),
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                f64::to_radians(30.),
+                16,
+                12,
+            ),
+            Vec3::new(0., 0., 0.),
+            vec![Box::new(Sphere::new(
+                Vec3::new(0., 0., 0.),
+                2.,
+                matte(1.).into(),
+            ))],
+            vec![Box::new(Point::new(
+                Vec3::new(0., 30., 30.),
+                1.,
+                Vec3::new(1., 1., 1.),
+            ))],
+        );
+        let image = render(&unscaled, &Whitted, &RenderSettings::default());
+        assert_eq!(image.pixels().filter(|p| p[0] == 0.).count(), unlit);
+    }
+
+    #[test]
+    fn test_render_parallel_deterministic() {
+        let scene = test_scene(77, 45); // Not a multiple of the tile size on purpose
+        let settings = |threads| RenderSettings {
+            threads,
+            samples: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&scene, &Whitted, &settings(1)),
+            render(&scene, &Whitted, &settings(7))
+        );
+    }
+
+    #[test]
+    fn test_bvh_matches_linear_scan() {
+        let objects: Vec<Box<dyn RayIntersect>> = (0..200)
+            .map(|i| {
+                let (x, y, z) = ((i % 7) as f64, (i % 11) as f64, -((i % 13) as f64) - 5.);
+                Box::new(Sphere::new(
+                    Vec3::new(x - 3., y - 5., z),
+                    0.4,
+                    glass().into(),
+                )) as Box<dyn RayIntersect>
+            })
+            .collect();
+        let bvh = Bvh::build(&objects);
+
+        for i in 0..400 {
+            let dir =
+                Vec3::new((i % 20) as f64 / 10. - 1., (i / 20) as f64 / 10. - 1., -1.).normalize();
+            let origin = Vec3::new(0., 0., 0.);
+
+            let linear = objects
+                .iter()
+                .filter_map(|obj| obj.ray_intersect(origin, dir))
+                .map(|intersection| intersection.dist)
+                .fold(f64::INFINITY, f64::min);
+            let accelerated = bvh
+                .closest_hit(&objects, origin, dir)
+                .map_or(f64::INFINITY, |(_, intersection)| intersection.dist);
+
+            assert_eq!(linear, accelerated);
+            assert_eq!(
+                linear < 10.,
+                bvh.any_hit(&objects, origin, dir, 10.),
+                "any_hit disagrees for ray {i}"
+            );
+        }
+    }
+
+    const SCENE_SOURCE: &str = r#"
+background_color = [0.2, 0.7, 0.8]
+
+[camera]
+width = 16
+height = 12
+fov = 60.0
+look_at = [0.0, 0.0, -1.0]
+
+[materials.glass]
+diffuse_color = [0.6, 0.7, 0.8]
+diffuse_albedo = 0.1
+specular_exponent = 125.0
+specular_albedo = 0.5
+reflective_albedo = 0.1
+refractive_index = 1.5
+refractive_albedo = 0.8
+
+[[objects.sphere]]
+center = [-1.0, 0.0, -8.0]
+radius = 2.0
+material = "glass"
+
+[[objects.sphere]]
+center = [2.0, 1.0, -12.0]
+radius = 3.0
+material = "glass"
+
+[[lights.point]]
+position = [-20.0, 20.0, 20.0]
+intensity = 1.5
+"#;
+
+    #[test]
+    fn test_load_scene() {
+        let scene: Scene = SCENE_SOURCE.parse().unwrap();
+        assert_eq!(2, scene.objects().len());
+        assert_eq!(1, scene.lights.len());
+        assert_eq!(
+            render_with_defaults(&test_scene(16, 12)),
+            render_with_defaults(&scene)
+        );
+    }
+
+    #[test]
+    fn test_load_scene_errors() {
+        let unknown_material =
+            SCENE_SOURCE.replacen(r#"material = "glass""#, r#"material = "ice""#, 1);
+        match unknown_material.parse::<Scene>() {
+            Err(SceneError::Parse { line, column, .. }) => assert_eq!((22, 12), (line, column)),
+            _ => panic!("Unknown material must be reported"),
+        }
+
+        let bad_vector = SCENE_SOURCE.replace("[2.0, 1.0, -12.0]", "[2.0, 1.0]");
+        match bad_vector.parse::<Scene>() {
+            Err(SceneError::Parse { line, .. }) => assert_eq!(25, line),
+            _ => panic!("Bad vector must be reported"),
+        }
+
+        // Looking straight down the default up axis points at the view direction
+        let parallel_up = SCENE_SOURCE.replace("[0.0, 0.0, -1.0]", "[0.0, -5.0, 0.0]");
+        match parallel_up.parse::<Scene>() {
+            Err(SceneError::Parse { line, column, .. }) => assert_eq!((8, 11), (line, column)),
+            _ => panic!("Parallel up must be reported"),
+        }
+
+        for (bad_value, position) in [
+            (("radius = 3.0", "radius = 0.0"), (26, 10)),
+            (("fov = 60.0", "fov = 180.0"), (7, 7)),
+            (("fov = 60.0", "fov = -5.0"), (7, 7)),
+        ] {
+            match SCENE_SOURCE
+                .replace(bad_value.0, bad_value.1)
+                .parse::<Scene>()
+            {
+                Err(SceneError::Parse { line, column, .. }) => {
+                    assert_eq!(position, (line, column))
+                }
+                _ => panic!("{} must be reported", bad_value.1),
+            }
+        }
+
+        let rectangle_light = |edge_b: &str, samples: usize| {
+            format!(
+                "{SCENE_SOURCE}\n[[lights.rectangle]]\ncenter = [0.0, 5.0, 0.0]\n\
+                 edge_a = [1.0, 0.0, 0.0]\nedge_b = {edge_b}\nintensity = 1.0\n\
+                 samples = {samples}\n"
+            )
+        };
+        let light_line = SCENE_SOURCE.lines().count() + 2;
+        for (bad_light, position) in [
+            (rectangle_light("[0.0, 0.0, 0.0]", 4), (light_line + 3, 10)),
+            (rectangle_light("[-2.0, 0.0, 0.0]", 4), (light_line + 3, 10)),
+            (rectangle_light("[0.0, 0.0, 1.0]", 0), (light_line + 5, 11)),
+            (
+                format!(
+                    "{SCENE_SOURCE}\n[[lights.ambient]]\nintensity = 0.1\n\n\
+                     [lights.ambient.occlusion]\ndistance = 1.0\nsamples = 0\n"
+                ),
+                (light_line + 5, 11),
+            ),
+        ] {
+            match bad_light.parse::<Scene>() {
+                Err(SceneError::Parse { line, column, .. }) => {
+                    assert_eq!(position, (line, column))
+                }
+                _ => panic!("Broken light must be reported"),
+            }
+        }
+        assert!(rectangle_light("[0.0, 0.0, 1.0]", 1)
+            .parse::<Scene>()
+            .is_ok());
+
+        // Of several broken textures, the first by name is reported
+        let broken_textures = format!(
+            "{SCENE_SOURCE}\n[textures.b.gradient]\ndirection = [0.0, 0.0, 0.0]\n\n\
+             [textures.a.gradient]\ndirection = [0.0, 0.0, 0.0]\n"
+        );
+        let broken_line = SCENE_SOURCE.lines().count() + 6;
+        for _ in 0..8 {
+            match broken_textures.parse::<Scene>() {
+                Err(SceneError::Parse { line, .. }) => assert_eq!(broken_line, line),
+                _ => panic!("Zero gradient direction must be reported"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_triangle_intersect() {
+        let triangle = Triangle::new(
+            [
+                Vec3::new(-1., -1., -5.),
+                Vec3::new(1., -1., -5.),
+                Vec3::new(0., 1., -5.),
+            ],
+            glass().into(),
+        );
+        let origin = Vec3::new(0., 0., 0.);
+
+        let hit = triangle
+            .ray_intersect(origin, Vec3::new(0., 0., -1.))
+            .unwrap();
+        assert_eq!(5., hit.dist);
+        assert_eq!(Vec3::new(0., 0., -5.), hit.hit_point);
+        assert_eq!(Vec3::new(0., 0., 1.), hit.norm_dir);
+        assert_eq!((0.25, 0.5), hit.uv);
+
+        assert!(triangle
+            .ray_intersect(origin, Vec3::new(1., 1., -1.).normalize())
+            .is_none());
+        assert!(triangle
+            .ray_intersect(origin, Vec3::new(0., 0., 1.))
+            .is_none());
+
+        // Seen from behind, only the normal of a lone triangle turns to face the ray
+        let behind = Vec3::new(0., 0., -10.);
+        let back = triangle
+            .ray_intersect(behind, Vec3::new(0., 0., 1.))
+            .unwrap();
+        assert_eq!(Vec3::new(0., 0., -1.), back.norm_dir);
+        let back = triangle
+            .closed()
+            .ray_intersect(behind, Vec3::new(0., 0., 1.))
+            .unwrap();
+        assert_eq!(Vec3::new(0., 0., 1.), back.norm_dir);
+    }
+
+    #[test]
+    fn test_uv_mapping() {
+        let sphere = Sphere::new(Vec3::new(0., 0., 0.), 1., glass().into());
+        let front = sphere
+            .ray_intersect(Vec3::new(0., 0., 5.), Vec3::new(0., 0., -1.))
+            .unwrap();
+        assert!((front.uv.0 - 0.25).abs() < 1e-9 && (front.uv.1 - 0.5).abs() < 1e-9);
+        let top = sphere
+            .ray_intersect(Vec3::new(0., 5., 0.), Vec3::new(0., -1., 0.))
+            .unwrap();
+        assert!((top.uv.1 - 1.).abs() < 1e-9);
+
+        // u runs along plane_vector_a and v along the normal crossed with it, here +z
+        let disk = CircularPlane::new(
+            Vec3::new(0., 0., 0.),
+            2.,
+            glass().into(),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 0., 1.),
+        );
+        let hit = disk
+            .ray_intersect(
+                Vec3::new(0.7, 1., 0.9),
+                Vec3::new(0.1, -1., 0.1).normalize(),
+            )
+            .unwrap();
+        assert!((hit.uv.0 - 0.7).abs() < 1e-9 && (hit.uv.1 - 0.75).abs() < 1e-9);
+
+        let triangle = Triangle::new(
+            [
+                Vec3::new(0., 0., -1.),
+                Vec3::new(1., 0., -1.),
+                Vec3::new(0., 1., -1.),
+            ],
+            glass().into(),
+        )
+        .with_uvs([(0., 0.), (4., 0.), (0., 2.)]);
+        let hit = triangle
+            .ray_intersect(Vec3::new(0.25, 0.5, 0.), Vec3::new(0., 0., -1.))
+            .unwrap();
+        assert_eq!((1., 1.), hit.uv);
+    }
+
+    #[test]
+    fn test_image_textures() {
+        // Only the top left pixel is lit, UV (0.25, 0.75) is its center
+        let mut image = Rgb32FImage::new(2, 2);
+        image.put_pixel(0, 0, Rgb([1., 1., 1.]));
+        let texture = ImageTexture::new(image);
+        let red = |texture: &ImageTexture, u: f64, v: f64| texture.sample((u, v))[0];
+
+        assert_eq!(1., red(&texture, 0.25, 0.75));
+        assert_eq!(1., red(&texture, 1.25, -0.25));
+        assert_eq!(0.5, red(&texture, 0.5, 0.75));
+        assert_eq!(0.25, red(&texture, 0.5, 0.5));
+        assert_eq!(0.5, red(&texture, 0., 0.75));
+        let clamped = texture.clone().with_wrap(Wrap::Clamp);
+        assert_eq!(1., red(&clamped, 0., 0.75));
+        assert_eq!(0., red(&clamped, 2., 0.75));
+        let mirrored = texture.clone().with_wrap(Wrap::Mirror);
+        assert_eq!(1., red(&mirrored, 0., 0.75));
+        assert_eq!(1., red(&mirrored, -0.25, 0.75));
+        let nearest = texture.clone().with_interpolation(Interpolation::Nearest);
+        assert_eq!(1., red(&nearest, 0.45, 0.55));
+        assert_eq!(1., red(&texture.with_scale(2.), 0.625, 0.875));
+
+        // 8-bit files are sRGB encoded
+        let path = std::env::temp_dir().join("raytracer_test_texture.png");
+        RgbImage::from_pixel(1, 1, Rgb([188, 0, 255]))
+            .save(&path)
+            .unwrap();
+        let source = SCENE_SOURCE.replace(
+            "[materials.glass]",
+            &format!(
+                "[textures.gray.image]\npath = {:?}\n\n[materials.glass]\ntextures = {{ diffuse_color = \"gray\" }}",
+                path.display().to_string()
+            ),
+        );
+        let scene: Scene = source.parse().unwrap();
+        let color = phong(&scene.material_at(0, (0.3, 0.6), Vec3::new(0., 0., 0.))).diffuse_color;
+        assert!((color[0] - 0.6 * 0.5).abs() < 0.002, "{color:?}");
+        assert_eq!((0., 0.8), (color[1], color[2]));
+        assert_eq!(
+            Some(TextureId(0)),
+            phong(scene.objects()[0].get_material())
+                .textures
+                .diffuse_color
+        );
+
+        let unknown = source.replace(r#"= "gray""#, r#"= "grey""#);
+        match unknown.parse::<Scene>() {
+            Err(SceneError::Parse { message, .. }) => assert!(message.contains("grey")),
+            _ => panic!("Unknown texture must be reported"),
+        }
+
+        // Unless they are normal or bump maps, which hold data rather than colors
+        let normal_map = source.replace("diffuse_color = \"gray\"", "normal = \"gray\"");
+        let scene: Scene = normal_map.parse().unwrap();
+        let color = scene
+            .texture(TextureId(0))
+            .color((0.3, 0.6), Vec3::new(0., 0., 0.));
+        assert!((color[0] - 188. / 255.).abs() < 1e-6, "{color:?}");
+
+        let decoded = normal_map.replace(
+            "[textures.gray.image]",
+            "[textures.gray.image]\nlinear = false",
+        );
+        match decoded.parse::<Scene>() {
+            Err(SceneError::Parse { message, .. }) => assert!(message.contains("linear")),
+            _ => panic!("sRGB normal maps must be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_procedural_textures() {
+        let black = Vec3::new(0., 0., 0.);
+        let white = Vec3::new(1., 1., 1.);
+        let origin = Vec3::new(0., 0., 0.);
+
+        let checker = Procedural::new(Pattern::Checker, [black, white]).with_scale(2.);
+        assert_eq!(black, checker.color((0.1, 0.1), origin));
+        assert_eq!(white, checker.color((0.6, 0.1), origin));
+        assert_eq!(black, checker.color((0.6, 0.6), origin));
+        let solid = checker.with_mapping(Mapping::Position).with_scale(1.);
+        assert_eq!(black, solid.color((0.6, 0.1), Vec3::new(0.5, 0.5, 0.5)));
+        assert_eq!(white, solid.color((0.6, 0.1), Vec3::new(0.5, -0.5, 0.5)));
+
+        let gradient = Procedural::new(
+            Pattern::Gradient {
+                direction: Vec3::new(2., 0., 0.),
+            },
+            [black, white],
+        )
+        .with_mapping(Mapping::Position);
+        assert_eq!(0.5, gradient.value((0., 0.), Vec3::new(1., 7., 0.)));
+        assert_eq!(1., gradient.value((0., 0.), Vec3::new(3., 0., 0.)));
+
+        // Noise vanishes on the lattice and is otherwise fully determined by the seed
+        let perlin = Perlin::new(1);
+        assert_eq!(0., perlin.noise(Vec3::new(3., -2., 5.)));
+        let points: Vec<_> = (0..100)
+            .map(|i| Vec3::new(i as f64 * 0.37, i as f64 * 0.11, -(i as f64) * 0.23))
+            .collect();
+        assert!(points.iter().all(|&p| perlin.noise(p).abs() <= 1.));
+        assert!(points
+            .iter()
+            .any(|&p| perlin.noise(p) != Perlin::new(2).noise(p)));
+
+        for pattern in [
+            Pattern::Noise,
+            Pattern::Turbulence { octaves: 4 },
+            Pattern::Marble {
+                octaves: 4,
+                distortion: 5.,
+            },
+            Pattern::Wood { distortion: 0.5 },
+        ] {
+            let texture = |seed| {
+                Procedural::new(pattern, [black, white])
+                    .with_mapping(Mapping::Position)
+                    .with_seed(seed)
+            };
+            let values: Vec<_> = points
+                .iter()
+                .map(|&p| texture(7).value((0., 0.), p))
+                .collect();
+            assert!(values.iter().all(|value| (0. ..=1.).contains(value)));
+            assert!(
+                values.iter().any(|&value| value != values[0]),
+                "{pattern:?}"
+            );
+            assert!(
+                points
+                    .iter()
+                    .zip(&values)
+                    .all(|(&p, &value)| texture(7).value((0., 0.), p) == value),
+                "{pattern:?}"
+            );
+        }
+
+        // Textures can drive parameters other than colors
+        let mut material = matte(0.8);
+        material.textures.diffuse_albedo = Some(TextureId(0));
+        let scene = Scene::new(
+            Cam::look_at(white, origin, Vec3::new(0., 1., 0.), 1., 1, 1),
+            black,
+            vec![Box::new(Sphere::new(origin, 1., material.into()))],
+            vec![],
+        )
+        .with_textures(vec![Box::new(gradient)]);
+        let material = phong(&scene.material_at(0, (0.5, 0.5), Vec3::new(0.5, 0., 0.)));
+        assert!((material.diffuse_albedo - 0.2).abs() < 1e-12);
+        assert_eq!(white, material.diffuse_color);
+
+        let source = SCENE_SOURCE.replace(
+            "[materials.glass]",
+            "[textures.tiles.checker]\ncolors = [[0.1, 0.1, 0.1], [0.9, 0.9, 0.9]]\nscale = 8.0\n\n\
+             [textures.stone.marble]\nmapping = \"position\"\nseed = 3\ndistortion = 4.0\n\n\
+             [materials.glass]\ntextures = { diffuse_color = \"tiles\", specular_albedo = \"stone\" }",
+        );
+        let scene: Scene = source.parse().unwrap();
+        let textures = phong(scene.objects()[0].get_material()).textures;
+        assert!(textures.diffuse_color.is_some() && textures.specular_albedo.is_some());
+        assert_ne!(textures.diffuse_color, textures.specular_albedo);
+    }
+
+    #[test]
+    fn test_normal_and_bump_maps() {
+        // Nearby hits move along the tangent and bitangent by their UV difference
+        let objects: Vec<Box<dyn RayIntersect>> = vec![
+            Box::new(Sphere::new(Vec3::new(0.5, 0.2, -5.), 1.5, glass().into())),
+            Box::new(CircularPlane::new(
+                Vec3::new(0., 0., -5.),
+                3.,
+                glass().into(),
+                Vec3::new(1., 1., 0.),
+                Vec3::new(0., 0.3, 1.),
+            )),
+            Box::new(
+                Triangle::new(
+                    [
+                        Vec3::new(-2., -2., -5.),
+                        Vec3::new(2., -1., -5.),
+                        Vec3::new(0., 2., -4.),
+                    ],
+                    glass().into(),
+                )
+                .with_uvs([(0.2, 0.1), (0.9, 0.3), (0.4, 0.8)]),
+            ),
+        ];
+        let origin = Vec3::new(0.1, 0.2, 0.);
+        for object in &objects {
+            let hit = |dir: Vec3<f64>| object.ray_intersect(origin, dir.normalize()).unwrap();
+            let a = hit(Vec3::new(0.05, 0.03, -1.));
+            let b = hit(Vec3::new(0.0501, 0.0301, -1.));
+            let (du, dv) = (b.uv.0 - a.uv.0, b.uv.1 - a.uv.1);
+            let predicted = a.hit_point + a.tangent * du + a.bitangent * dv;
+            let moved = (b.hit_point - a.hit_point).norm();
+            assert!((predicted - b.hit_point).norm() < 1e-3 * moved, "{a:?}");
+        }
+
+        // A floor facing up with u along +x
+        let flat = Vec3::new(0.5, 0.5, 1.);
+        let floor = |textures: MaterialTextures, texture: Procedural| {
+            let mut material = matte(1.);
+            material.textures = textures;
+            Scene::new(
+                Cam::look_at(
+                    Vec3::new(0.3, 2., 0.2),
+                    Vec3::new(0., 0., 0.),
+                    Vec3::new(0., 0., -1.),
+                    1.,
+                    8,
+                    8,
+                ),
+                Vec3::new(0., 0., 0.),
+                vec![Box::new(CircularPlane::new(
+                    Vec3::new(0., 0., 0.),
+                    2.,
+                    material.into(),
+                    Vec3::new(1., 0., 0.),
+                    Vec3::new(0., 0., 1.),
+                ))],
+                vec![Box::new(Point::new(
+                    Vec3::new(1., 3., 1.),
+                    1.,
+                    Vec3::new(1., 1., 1.),
+                ))],
+            )
+            .with_textures(vec![Box::new(texture)])
+        };
+        let normal_at = |scene: &Scene| {
+            let dir = Vec3::new(-0.1, -1., -0.1).normalize();
+            let (_, props) = intersect_objects(Vec3::new(0.2, 2., 0.3), dir, scene).unwrap();
+            let material = scene.material_at(props.object_id, props.uv, props.hit_point);
+            shading_normal(&material, &props, scene)
+        };
+        let normal_map = |color: Vec3<f64>| {
+            floor(
+                MaterialTextures {
+                    normal: Some(TextureId(0)),
+                    ..MaterialTextures::default()
+                },
+                Procedural::new(Pattern::Checker, [color, color]),
+            )
+        };
+
+        let up = Vec3::new(0., 1., 0.);
+        assert!((normal_at(&normal_map(flat)) - up).norm() < 1e-12);
+        let tilted = normal_at(&normal_map(Vec3::new(0.75, 0.5, 1.)));
+        assert!((tilted - Vec3::new(0.5, 1., 0.).normalize()).norm() < 1e-12);
+        let plain = floor(
+            MaterialTextures::default(),
+            Procedural::new(Pattern::Checker, [flat; 2]),
+        );
+        let image = render_with_defaults(&normal_map(flat));
+        for (a, b) in image.pixels().zip(render_with_defaults(&plain).pixels()) {
+            assert!((0..3).all(|i| (a[i] - b[i]).abs() < 1e-6));
+        }
+
+        // Height growing with u by 4 per UV unit rises by 1 per unit along x on a disk of radius 2
+        let ramp = Procedural::new(
+            Pattern::Gradient {
+                direction: Vec3::new(1., 0., 0.),
+            },
+            [Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.)],
+        );
+        let bumped = floor(
+            MaterialTextures {
+                height: Some((TextureId(0), 4.)),
+                ..MaterialTextures::default()
+            },
+            ramp,
+        );
+        let expected = Vec3::new(-1., 1., 0.).normalize();
+        assert!((normal_at(&bumped) - expected).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_mesh_from_obj() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/models/pyramid.obj");
+        let mesh = Mesh::from_obj(&path, glass().into(), &HashMap::new()).unwrap();
+        assert_eq!(6, mesh.triangles().len()); // 4 sides and a triangulated quad base
+        assert!(mesh.triangles().iter().all(|i| i.is_closed()));
+
+        let gold = phong(mesh.triangles()[0].get_material());
+        assert_eq!(Vec3::new(0.8, 0.6, 0.2), gold.diffuse_color);
+        assert_eq!(80., gold.specular_exponent);
+
+        let overridden = Mesh::from_obj(
+            &path,
+            glass().into(),
+            &HashMap::from([("base".to_owned(), glass().into())]),
+        )
+        .unwrap();
+        assert_eq!(glass(), phong(overridden.triangles()[5].get_material()));
+        assert_ne!(glass(), phong(overridden.triangles()[0].get_material()));
+
+        // Groups fall back to the default material when the MTL library can't be loaded
+        let path = std::env::temp_dir().join("raytracer_test_missing_mtl.obj");
+        std::fs::write(
+            &path,
+            "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl gold\nf 1 2 3\n",
+        )
+        .unwrap();
+        let fallback = Mesh::from_obj(&path, glass().into(), &HashMap::new()).unwrap();
+        assert_eq!(1, fallback.triangles().len());
+        assert_eq!(glass(), phong(fallback.triangles()[0].get_material()));
+
+        // The triangles of a loaded mesh bound a single volume
+        let scene =
+            Scene::from_file(Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/mesh.toml"))
+                .unwrap();
+        assert_eq!(8, scene.objects().len());
+        assert_eq!(
+            vec![0, 1, 2, 2, 2, 2, 2, 2],
+            (0..8).map(|i| scene.volume_id(i)).collect::<Vec<_>>()
+        );
+
+        // Overrides must name groups of the file
+        assert_eq!(["sides", "base"], mesh.groups());
+        let scenes_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let source = std::fs::read_to_string(scenes_dir.join("mesh.toml")).unwrap();
+        let with_overrides = |overrides: &str| {
+            source.replacen(
+                "material = \"red_rubber\"\n",
+                &format!("material = \"red_rubber\"\nmaterials = {{ {overrides} }}\n"),
+                1,
+            )
+        };
+        assert!(load_scene(&with_overrides(r#"sides = "glass""#), &scenes_dir).is_ok());
+        match load_scene(
+            &with_overrides(r#"sides = "glass", roof = "glass""#),
+            &scenes_dir,
+        ) {
+            Err(SceneError::Parse { line, column, .. }) => assert_eq!((32, 32), (line, column)),
+            _ => panic!("Unknown group must be reported"),
+        }
+    }
+
+    #[test]
+    fn test_seeded_supersampling() {
+        let scene = test_scene(40, 30);
+        let settings = |filter, seed| RenderSettings {
+            samples: 9,
+            filter,
+            seed,
+            ..Default::default()
+        };
+
+        for filter in [
+            Filter::Box,
+            Filter::Tent,
+            Filter::Gaussian,
+            Filter::Mitchell,
+        ] {
+            assert_eq!(
+                render(&scene, &Whitted, &settings(filter, 42)),
+                render(&scene, &Whitted, &settings(filter, 42))
+            );
+            assert_eq!(0., filter.weight(filter.radius() + 0.01, 0.));
+        }
+        assert_ne!(
+            render(&scene, &Whitted, &settings(Filter::Tent, 1)),
+            render(&scene, &Whitted, &settings(Filter::Tent, 2))
+        );
+
+        // Counts that aren't squares are neither rounded nor truncated
+        let mut rng = Rng::new(1);
+        for samples in 0..20 {
+            let points = stratified(samples, &mut rng);
+            assert_eq!(samples, points.len());
+            assert!(points
+                .iter()
+                .all(|&(u, v)| (0. ..1.).contains(&u) && (0. ..1.).contains(&v)));
+        }
+    }
+
+    #[test]
+    fn test_tone_mapping() {
+        let hdr: Rgb32FImage = ImageBuffer::from_fn(4, 1, |x, _| {
+            let value = [-1., 0., 1., 10.][x as usize];
+            Rgb([value, value, value])
+        });
+
+        for operator in [
+            ToneMapOperator::Clamp,
+            ToneMapOperator::Exposure,
+            ToneMapOperator::Reinhard,
+            ToneMapOperator::Aces,
+        ] {
+            let ldr = ToneMapping {
+                operator,
+                ..Default::default()
+            }
+            .apply(&hdr);
+            let values: Vec<u8> = ldr.pixels().map(|i| i[0]).collect();
+            assert_eq!(0, values[0], "{operator} must clamp negative values");
+            assert_eq!(0, values[1]);
+            assert!(values[1] < values[2] && values[2] <= values[3]);
+        }
+
+        let linear = ToneMapping {
+            encoding: Encoding::Gamma(1.),
+            ..Default::default()
+        };
+        assert_eq!(Rgb([255, 255, 255]), *linear.apply(&hdr).get_pixel(3, 0));
+        let srgb =
+            ToneMapping::default().apply(&ImageBuffer::from_pixel(1, 1, Rgb([0.5, 0.5, 0.5])));
+        assert_eq!(Rgb([188, 188, 188]), *srgb.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn test_fresnel() {
+        for fresnel in [Fresnel::Schlick, Fresnel::Exact] {
+            assert!((fresnel.reflectance(1., 1., 1.5) - 0.04).abs() < 1e-9);
+            assert!(fresnel.reflectance(0.1, 1., 1.5) > fresnel.reflectance(0.9, 1., 1.5));
+            // Past the critical angle of about 41.8 degrees when leaving glass
+            assert_eq!(1., fresnel.reflectance(f64::to_radians(50.).cos(), 1.5, 1.));
+        }
+
+        let norm = Vec3::new(0., 1., 0.);
+        let grazing_from_inside = Vec3::new(1., 0.2, 0.).normalize();
+        assert_eq!(None, refract(grazing_from_inside, norm, 1.5, 1.));
+        assert!(refract(-grazing_from_inside, norm, 1., 1.5).is_some());
+    }
+
+    #[test]
+    fn test_nested_media() {
+        let with_index = |refractive_index| {
+            Material::Phong(Phong {
+                refractive_index,
+                ..glass()
+            })
+        };
+        let water = Sphere::new(Vec3::new(0., 0., 0.), 2., with_index(1.33));
+        let glass = Sphere::new(Vec3::new(0., 0., 0.), 1., with_index(1.5));
+        let bubble = Sphere::new(Vec3::new(0., 0., 0.), 0.5, with_index(1.));
+
+        let crossings = |path: &[(&Sphere, usize, bool)]| {
+            let mut media = MediaStack::new();
+            let mut interfaces = Vec::new();
+            for &(obj, volume, entering) in path {
+                let interface = media.interface(obj, volume, entering);
+                interfaces.push((interface.n1, interface.n2));
+                media = media.refracted(&interface, obj);
+            }
+            assert_eq!(MediaStack::new(), media);
+            interfaces
+        };
+
+        assert_eq!(
+            vec![
+                (1., 1.33),
+                (1.33, 1.5),
+                (1.5, 1.),
+                (1., 1.5),
+                (1.5, 1.33),
+                (1.33, 1.)
+            ],
+            crossings(&[
+                (&water, 0, true),
+                (&glass, 1, true),
+                (&bubble, 2, true),
+                (&bubble, 2, false),
+                (&glass, 1, false),
+                (&water, 0, false),
+            ])
+        );
+
+        // Leaving one of two overlapping volumes of equal index keeps the ray in the other
+        let other_glass = Sphere::new(Vec3::new(0.5, 0., 0.), 1., with_index(1.5));
+        assert_eq!(
+            vec![
+                (1., 1.5),
+                (1.5, 1.33),
+                (1.33, 1.5),
+                (1.5, 1.5),
+                (1.5, 1.33),
+                (1.33, 1.)
+            ],
+            crossings(&[
+                (&glass, 1, true),
+                (&water, 0, true),
+                (&other_glass, 3, true),
+                (&glass, 1, false),
+                (&other_glass, 3, false),
+                (&water, 0, false),
+            ])
+        );
+
+        // A lone triangle is a thin pane rather than the boundary of a volume
+        let pane = Triangle::new(
+            [
+                Vec3::new(0., 0., 0.),
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 1., 0.),
+            ],
+            with_index(1.5),
+        );
+        let interface = MediaStack::new().interface(&pane, 4, true);
+        assert_eq!((1., 1.), (interface.n1, interface.n2));
+        assert_eq!(
+            MediaStack::new(),
+            MediaStack::new().refracted(&interface, &pane)
+        );
+    }
+
+    #[test]
+    fn test_area_light_penumbra() {
+        let scene = Scene::new(
+            test_scene(1, 1).cam,
+            Vec3::new(0., 0., 0.),
+            vec![Box::new(Sphere::new(
+                Vec3::new(0., 2., 0.),
+                1.5,
+                Material::Phong(Phong {
+                    refractive_albedo: 0.,
+                    ..glass()
+                }),
+            ))],
+            vec![],
+        );
+        let light = Rectangle::new(
+            Vec3::new(0., 4., 0.),
+            Vec3::new(4., 0., 0.),
+            Vec3::new(0., 0., 4.),
+            1.,
+            Vec3::new(1., 1., 1.),
+            64,
+        );
+        let mut rng = Rng::new(0);
+        let up = Vec3::new(0., 1., 0.);
+
+        let umbra = light.transmission(Vec3::new(0., 0., 0.), up, &scene, 1e-3, &mut rng)[0];
+        let penumbra = light.transmission(Vec3::new(3., 0., 0.), up, &scene, 1e-3, &mut rng)[0];
+        let lit = light.transmission(Vec3::new(20., 0., 0.), up, &scene, 1e-3, &mut rng)[0];
+        assert!(
+            umbra < penumbra && penumbra < lit,
+            "{umbra} {penumbra} {lit}"
+        );
+        assert_eq!(1., lit);
+    }
+
+    #[test]
+    fn test_directional_spot_ambient_lights() {
+        let up = Vec3::new(0., 1., 0.);
+        let white = Vec3::new(1., 1., 1.);
+
+        let sun = Directional::new(Vec3::new(0., -1., 0.), 0.5, white);
+        for point in [Vec3::new(0., 0., 0.), Vec3::new(1000., 0., -1000.)] {
+            assert_eq!(0.5, sun.get_diffuse_light_intensity(point, up));
+        }
+
+        let spot = Spot::new(
+            Vec3::new(0., 10., 0.),
+            Vec3::new(0., -1., 0.),
+            f64::to_radians(30.),
+            f64::to_radians(10.),
+            2.,
+            white,
+        );
+        let intensity_at = |x: f64| spot.get_diffuse_light_intensity(Vec3::new(x, 0., 0.), up);
+        assert!((intensity_at(0.) - 2.).abs() < 1e-9);
+        let edge = intensity_at(10. * f64::to_radians(25.).tan());
+        assert!(0. < edge && edge < intensity_at(1.));
+        assert_eq!(0., intensity_at(10.));
+
+        let ambient = Ambient::new(0.1, white);
+        let mut rng = Rng::new(0);
+        let scene = test_scene(1, 1);
+        assert_eq!(
+            0.1,
+            ambient.get_diffuse_light_intensity(Vec3::new(0., 0., -8.), -up)
+        );
+        assert_eq!(
+            white,
+            ambient.transmission(Vec3::new(-1., 0., -8.), up, &scene, 1e-3, &mut rng)
+        );
+    }
+
+    #[test]
+    fn test_colored_light_attenuation() {
+        let white = Vec3::new(1., 1., 1.);
+        let light = Point::new(Vec3::new(0., 1., 0.), 1., white)
+            .with_attenuation(Attenuation::InverseSquare);
+        let up = Vec3::new(0., 1., 0.);
+        let near = light.get_diffuse_light_intensity(Vec3::new(0., 0., 0.), up);
+        let far = light.get_diffuse_light_intensity(Vec3::new(0., -1., 0.), up);
+        assert!((near - 4. * far).abs() < 1e-9);
+
+        let scene = Scene::new(
+            Cam::look_at(
+                Vec3::new(0., 5., 0.),
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 0., -1.),
+                f64::to_radians(20.),
+                8,
+                2,
+            ),
+            Vec3::new(0., 0., 0.),
+            vec![Box::new(CircularPlane::new(
+                Vec3::new(0., 0., 0.),
+                10.,
+                matte(1.).into(),
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 0., 1.),
+            ))],
+            vec![
+                Box::new(
+                    Point::new(Vec3::new(-2., 1., 0.), 1., Vec3::new(1., 0., 0.))
+                        .with_attenuation(Attenuation::InverseSquare),
+                ),
+                Box::new(
+                    Point::new(Vec3::new(2., 1., 0.), 1., Vec3::new(0., 0., 1.))
+                        .with_attenuation(Attenuation::InverseSquare),
+                ),
+            ],
+        );
+
+        let image = render_with_defaults(&scene);
+        let left = image.get_pixel(0, 0);
+        let right = image.get_pixel(7, 0);
+        assert!(left[0] > 2. * left[2], "{left:?}");
+        assert!(right[2] > 2. * right[0], "{right:?}");
+        assert_eq!(0., left[1] + right[1]);
+    }
+
+    #[test]
+    fn test_transparent_shadows() {
+        let red_glass = Material::Phong(Phong {
+            diffuse_color: Vec3::new(0.9, 0.2, 0.2),
+            ..glass()
+        });
+        let opaque = Material::Phong(Phong {
+            refractive_albedo: 0.,
+            ..glass()
+        });
+        let scene = Scene::new(
+            test_scene(1, 1).cam,
+            Vec3::new(0., 0., 0.),
+            vec![
+                Box::new(Sphere::new(Vec3::new(0., 2., 0.), 1., red_glass)),
+                Box::new(Sphere::new(Vec3::new(10., 2., 0.), 1., opaque)),
+            ],
+            vec![],
+        );
+        let mut rng = Rng::new(0);
+        let transmission_at = |x: f64, rng: &mut Rng| {
+            Point::new(Vec3::new(x, 4., 0.), 1., Vec3::new(1., 1., 1.)).transmission(
+                Vec3::new(x, 0., 0.),
+                Vec3::new(0., 1., 0.),
+                &scene,
+                1e-3,
+                rng,
+            )
+        };
+
+        // Both surfaces of the sphere tint the light
+        let tinted = transmission_at(0., &mut rng);
+        let expected = Vec3::new(0.72 * 0.72, 0.16 * 0.16, 0.16 * 0.16);
+        for axis in 0..3 {
+            assert!((tinted[axis] - expected[axis]).abs() < 1e-9, "{tinted:?}");
+        }
+        assert_eq!(Vec3::new(0., 0., 0.), transmission_at(10., &mut rng));
+        assert_eq!(Vec3::new(1., 1., 1.), transmission_at(5., &mut rng));
+    }
+
+    #[test]
+    fn test_path_tracing() {
+        let path_tracing = RenderSettings {
+            background: Background::Color(Vec3::new(0., 0., 0.)),
+            ..RenderSettings::default()
+        };
+
+        // A convex object reflects its albedo of a uniformly white environment
+        let furnace = Scene::new(
+            test_scene(4, 4).cam,
+            Vec3::new(1., 1., 1.),
+            vec![Box::new(Sphere::new(
+                Vec3::new(0., 0., -3.),
+                2.5,
+                matte(0.5).into(),
+            ))],
+            vec![],
+        );
+        let image = render(
+            &furnace,
+            &PathTracer,
+            &RenderSettings {
+                samples: 4,
+                background: Background::Scene,
+                ..path_tracing
+            },
+        );
+        for pixel in image.pixels() {
+            assert!((pixel[0] - 0.5).abs() < 1e-6, "{pixel:?}");
+        }
+
+        // Direct light from a point light matches the Whitted integrator
+        let scene = Scene::new(
+            test_scene(8, 8).cam,
+            Vec3::new(0., 0., 0.),
+            vec![Box::new(Sphere::new(
+                Vec3::new(0., 0., -4.),
+                1.5,
+                matte(0.8).into(),
+            ))],
+            vec![Box::new(Point::new(
+                Vec3::new(-5., 5., 0.),
+                1.2,
+                Vec3::new(1., 0.5, 0.2),
+            ))],
+        );
+        let whitted = render_with_defaults(&scene);
+        let path = render(&scene, &PathTracer, &path_tracing);
+        for (a, b) in whitted.pixels().zip(path.pixels()) {
+            for channel in 0..3 {
+                assert!((a[channel] - b[channel]).abs() < 1e-3, "{a:?} {b:?}");
+            }
+        }
+
+        // A disk light of radiance L and radius R at height h lights the floor right under it
+        // with L * R^2 / (R^2 + h^2) times the albedo
+        let scene = Scene::new(
+            Cam::look_at(
+                Vec3::new(3., 1., 0.5),
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                f64::to_radians(0.1),
+                1,
+                1,
+            ),
+            Vec3::new(0., 0., 0.),
+            vec![Box::new(CircularPlane::new(
+                Vec3::new(0., 0., 0.),
+                100.,
+                matte(0.5).into(),
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 0., 1.),
+            ))],
+            vec![Box::new(Disk::new(
+                Vec3::new(0., 2., 0.),
+                Vec3::new(0., -1., 0.),
+                1.,
+                1.,
+                Vec3::new(1., 1., 1.),
+                1,
+            ))],
+        );
+        let image = render(
+            &scene,
+            &PathTracer,
+            &RenderSettings {
+                samples: 1024,
+                ..path_tracing
+            },
+        );
+        let pixel = image.get_pixel(0, 0);
+        assert!((pixel[0] - 0.1).abs() < 0.003, "{pixel:?}");
+    }
+
+    #[test]
+    fn test_pbr_materials() {
+        let sphere = Sphere::new(Vec3::new(0., 0., 0.), 1., matte(1.).into());
+        let interface = MediaStack::new().interface(&sphere, 0, true);
+        let dir = Vec3::new(1., -2., 0.).normalize();
+        let up = Vec3::new(0., 1., 0.);
+        let mut rng = Rng::new(1);
+
+        // Sampled weights and evaluations at uniform directions estimate the same share of
+        // uniform light reflected, which can't exceed 1
+        for roughness in [0.5, 1.] {
+            for metallic in [0., 1.] {
+                let material = Material::Pbr(Pbr {
+                    metallic,
+                    roughness,
+                    ..Pbr::default()
+                });
+                let bsdf = material.bsdf(dir, up, &interface);
+                let samples = 100000;
+                let (mut sampled, mut uniform) = (0., 0.);
+                for _ in 0..samples {
+                    if let Some(sample) = bsdf.sample(&mut rng) {
+                        sampled += sample.weight[0];
+                    }
+                    let light_dir = uniform_sphere(rng.next_f64(), rng.next_f64());
+                    uniform += bsdf.eval(light_dir)[0] * (light_dir * up).max(0.) * 4. * PI;
+                }
+                let (sampled, uniform) = (sampled / samples as f64, uniform / samples as f64);
+                assert!(sampled < 1., "{sampled}");
+                assert!((sampled - uniform).abs() < 0.02, "{sampled} {uniform}");
+            }
+        }
+
+        // A smooth metal mirrors its surroundings tinted by its base color
+        let gold = Pbr {
+            base_color: Vec3::new(1., 0.8, 0.3),
+            roughness: 0.,
+            ..Pbr::default()
+        };
+        let mirror = Scene::new(
+            Cam::look_at(
+                Vec3::new(0., 0., 5.),
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                0.01,
+                1,
+                1,
+            ),
+            Vec3::new(0.5, 0.5, 0.5),
+            vec![Box::new(Sphere::new(
+                Vec3::new(0., 0., 0.),
+                1.,
+                gold.into(),
+            ))],
+            vec![],
+        );
+        let settings = RenderSettings {
+            background: Background::Scene,
+            ..RenderSettings::default()
+        };
+        let pixel = *render(&mirror, &Whitted, &settings).get_pixel(0, 0);
+        assert!((pixel[1] - 0.4).abs() < 1e-3, "{pixel:?}");
+        assert!((pixel[2] - 0.15).abs() < 1e-3, "{pixel:?}");
+
+        let source = format!(
+            "{SCENE_SOURCE}\n[pbr_materials.gold]\nbase_color = [1.0, 0.8, 0.3]\nroughness = 0.0\n\n\
+             [[objects.sphere]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"gold\"\n"
+        );
+        let scene: Scene = source.parse().unwrap();
+        assert_eq!(&Material::Pbr(gold), scene.objects()[2].get_material());
+
+        let duplicate = format!("{SCENE_SOURCE}\n[pbr_materials.glass]\nmetallic = 0.0\n");
+        match duplicate.parse::<Scene>() {
+            Err(SceneError::Parse { message, .. }) => assert!(message.contains("defined twice")),
+            _ => panic!("Materials must have unique names"),
+        }
+    }
+
+    #[test]
+    fn test_emissive_objects() {
+        // Sampled points lie on the surface, with the UVs a ray hitting them would report
+        let objects: Vec<Box<dyn RayIntersect>> = vec![
+            Box::new(Sphere::new(Vec3::new(1., 2., 3.), 1.5, matte(1.).into())),
+            Box::new(CircularPlane::new(
+                Vec3::new(0., 1., 0.),
+                2.,
+                matte(1.).into(),
+                Vec3::new(1., 0., 1.),
+                Vec3::new(0., 1., 0.),
+            )),
+            Box::new(
+                Triangle::new(
+                    [
+                        Vec3::new(0., 0., 0.),
+                        Vec3::new(2., 0., 0.),
+                        Vec3::new(0., 1., 1.),
+                    ],
+                    matte(1.).into(),
+                )
+                .with_uvs([(0., 0.), (1., 0.), (0.5, 1.)]),
+            ),
+        ];
+        let mut rng = Rng::new(3);
+        for obj in &objects {
+            for _ in 0..20 {
+                let sample = obj.sample_surface(rng.next_f64(), rng.next_f64());
+                let origin = sample.point + Vec3::new(0.3, 0.5, 0.4) + sample.norm_dir;
+                let dir = (sample.point - origin).normalize();
+                let hit = obj.ray_intersect(origin, dir).unwrap();
+                assert!((hit.hit_point - sample.point).norm() < 1e-9);
+                assert!((hit.uv.0 - sample.uv.0).abs() < 1e-9, "{hit:?} {sample:?}");
+                assert!((hit.uv.1 - sample.uv.1).abs() < 1e-9, "{hit:?} {sample:?}");
+            }
+        }
+
+        // An emissive disk lights the floor like a disk light of the same radiance
+        let lamp = Phong {
+            emission: Vec3::new(0.5, 1., 0.25),
+            emission_strength: 2.,
+            ..matte(0.)
+        };
+        let scene = Scene::new(
+            Cam::look_at(
+                Vec3::new(3., 1., 0.5),
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                f64::to_radians(0.1),
+                1,
+                1,
+            ),
+            Vec3::new(0., 0., 0.),
+            vec![
+                Box::new(CircularPlane::new(
+                    Vec3::new(0., 0., 0.),
+                    100.,
+                    matte(0.5).into(),
+                    Vec3::new(1., 0., 0.),
+                    Vec3::new(0., 0., 1.),
+                )),
+                Box::new(CircularPlane::new(
+                    Vec3::new(0., 2., 0.),
+                    1.,
+                    lamp.into(),
+                    Vec3::new(1., 0., 0.),
+                    Vec3::new(0., 0., 1.),
+                )),
+            ],
+            vec![],
+        );
+        assert_eq!(&[1], scene.emitters());
+
+        let settings = RenderSettings {
+            background: Background::Color(Vec3::new(0., 0., 0.)),
+            samples: 256,
+            ..RenderSettings::default()
+        };
+        for integrator in [&Whitted as &dyn Integrator, &PathTracer] {
+            let pixel = *render(&scene, integrator, &settings).get_pixel(0, 0);
+            assert!((pixel[1] - 0.2).abs() < 0.006, "{pixel:?}");
+            assert!((pixel[0] - 0.1).abs() < 0.003, "{pixel:?}");
+        }
+
+        // The camera sees the emission itself
+        let mut scene = scene;
+        scene.cam = Cam::look_at(
+            Vec3::new(0., 0.5, 0.),
+            Vec3::new(0., 2., 0.),
+            Vec3::new(1., 0., 0.),
+            0.1,
+            1,
+            1,
+        );
+        for integrator in [&Whitted as &dyn Integrator, &PathTracer] {
+            let pixel = *render(&scene, integrator, &settings).get_pixel(0, 0);
+            assert!((pixel[0] - 1.).abs() < 1e-6, "{pixel:?}");
+            assert!((pixel[2] - 0.5).abs() < 1e-6, "{pixel:?}");
+        }
+
+        let source = SCENE_SOURCE.replace(
+            "refractive_albedo = 0.8",
+            "refractive_albedo = 0.8\nemission = [1.0, 1.0, 1.0]\nemission_strength = 3.0",
+        );
+        let scene: Scene = source.parse().unwrap();
+        assert_eq!(&[0, 1], scene.emitters());
+        assert_eq!(
+            Vec3::new(3., 3., 3.),
+            scene.objects()[0].get_material().emission()
+        );
+    }
+
+    #[test]
+    fn test_reflected_emitters() {
+        let lamp = Phong {
+            emission: Vec3::new(1., 1., 1.),
+            ..matte(0.)
+        };
+        // Whitted sees the lamp both in its mirror ray and by sampling it, which must add up to
+        // what a path tracer finds. Rougher metals are only roughly approximated by a mirror ray
+        for roughness in [0., 0.2] {
+            let metal = Pbr {
+                roughness,
+                ..Pbr::default()
+            };
+            let scene = Scene::new(
+                Cam::look_at(
+                    Vec3::new(-0.3, 1., 0.),
+                    Vec3::new(0., 0., 0.),
+                    Vec3::new(0., 1., 0.),
+                    f64::to_radians(0.1),
+                    1,
+                    1,
+                ),
+                Vec3::new(0., 0., 0.),
+                vec![
+                    Box::new(CircularPlane::new(
+                        Vec3::new(0., 0., 0.),
+                        100.,
+                        metal.into(),
+                        Vec3::new(1., 0., 0.),
+                        Vec3::new(0., 0., 1.),
+                    )),
+                    Box::new(CircularPlane::new(
+                        Vec3::new(0., 2., 0.),
+                        1.,
+                        lamp.into(),
+                        Vec3::new(1., 0., 0.),
+                        Vec3::new(0., 0., 1.),
+                    )),
+                ],
+                vec![],
+            );
+            let settings = RenderSettings {
+                background: Background::Color(Vec3::new(0., 0., 0.)),
+                samples: 1024,
+                ..RenderSettings::default()
+            };
+            let whitted = render(&scene, &Whitted, &settings).get_pixel(0, 0)[0];
+            let path = render(&scene, &PathTracer, &settings).get_pixel(0, 0)[0];
+            assert!(
+                (whitted - path).abs() < 0.07,
+                "{roughness} {whitted} {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_glossy_materials() {
+        let dir = Vec3::new(1., -2., 0.5).normalize();
+        let mut rng = Rng::new(5);
+        assert_eq!(dir, jitter(dir, 0., rng.next_f64(), rng.next_f64()));
+
+        // Rougher lobes spread further from the ideal direction, but never past it
+        let mut spread = |roughness: f64| {
+            let samples = 10000;
+            let mut mean = 0.;
+            for _ in 0..samples {
+                let jittered = jitter(dir, roughness, rng.next_f64(), rng.next_f64());
+                assert!((jittered.norm() - 1.).abs() < 1e-9);
+                assert!(jittered * dir >= 0.);
+                mean += jittered * dir;
+            }
+            mean / samples as f64
+        };
+        let (smooth, rough) = (spread(0.1), spread(1.));
+        assert!(smooth > 0.99, "{smooth}");
+        assert!((rough - 2. / 3.).abs() < 0.01, "{rough}");
+
+        // Rough mirrors and frosted glass scatter their rays to the correct side of the surface
+        let sphere = Sphere::new(Vec3::new(0., 0., 0.), 1., matte(1.).into());
+        let interface = MediaStack::new().interface(&sphere, 0, true);
+        let up = Vec3::new(0., 1., 0.);
+        let frosted: Material = Phong {
+            roughness: 0.5,
+            ..glass()
+        }
+        .into();
+        let bsdf = frosted.bsdf(dir, up, &interface);
+        let mut dirs = vec![];
+        for _ in 0..100 {
+            for sample in bsdf.specular_samples(&mut rng) {
+                assert_eq!(sample.refracted, sample.dir * up < 0.);
+                dirs.push(sample.dir);
+            }
+        }
+        assert!(dirs.iter().any(|d| (*d - dirs[0]).norm() > 0.1));
+
+        let sharp = Material::from(glass()).bsdf(dir, up, &interface);
+        let samples = sharp.specular_samples(&mut rng);
+        assert_eq!(2, samples.len());
+        assert_eq!(reflect(dir, up), samples[0].dir);
+
+        let source = SCENE_SOURCE.replace(
+            "refractive_albedo = 0.8",
+            "refractive_albedo = 0.8\nroughness = 0.25",
+        );
+        let scene: Scene = source.parse().unwrap();
+        assert_eq!(0.25, phong(scene.objects()[0].get_material()).roughness);
+    }
+
+    #[test]
+    fn test_debug_integrators() {
+        // The center ray hits the first sphere of the test scene 3^0.5 in front of its center
+        let scene = test_scene(1, 1);
+        let settings = RenderSettings::default();
+        let hit_z = -8. + 3f64.sqrt();
+
+        let depth = render(&scene, &Depth, &settings);
+        assert!((depth.get_pixel(0, 0)[0] as f64 + hit_z).abs() < 1e-5);
+
+        let normals = render(&scene, &Normals, &settings);
+        let expected = [0.75, 0.5, (1. + (hit_z + 8.) / 2.) / 2.];
+        for (channel, expected) in expected.into_iter().enumerate() {
+            assert!((normals.get_pixel(0, 0)[channel] as f64 - expected).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_ambient_occlusion() {
+        // Just below the first sphere of the test scene, facing it or facing away
+        let scene = test_scene(1, 1);
+        let point = Vec3::new(-1., -2.1, -8.);
+        let up = Vec3::new(0., 1., 0.);
+        let mut rng = Rng::new(0);
+
+        let under = ambient_occlusion(point, up, 64, f64::INFINITY, &scene, &mut rng);
+        assert!(under < 0.2, "{under}");
+        assert_eq!(1., ambient_occlusion(point, -up, 64, 10., &scene, &mut rng));
+        assert_eq!(1., ambient_occlusion(point, up, 64, 0.05, &scene, &mut rng));
+
+        let white = Vec3::new(1., 1., 1.);
+        let ambient = Ambient::new(0.1, white).with_occlusion(64, 1.);
+        let shade = ambient.transmission(point, up, &scene, 1e-3, &mut rng);
+        assert!(shade[0] < 0.2 && shade[0] == shade[2], "{shade:?}");
+        assert_eq!(
+            white,
+            ambient.transmission(point, -up, &scene, 1e-3, &mut rng)
+        );
+
+        // Nothing is in front of the sphere as seen from the camera
+        let image = render(
+            &scene,
+            &AmbientOcclusion::default(),
+            &RenderSettings::default(),
+        );
+        assert_eq!(&Rgb([1., 1., 1.]), image.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn test_aovs() {
+        let scene = test_scene(32, 24);
+        let settings = RenderSettings {
+            samples: 4,
+            ..RenderSettings::default()
+        };
+        let aovs = [
+            Aov::Depth,
+            Aov::ObjectId,
+            Aov::MaterialId,
+            Aov::Direct,
+            Aov::Indirect,
+        ];
+        let (image, layers) = render_with_aovs(&scene, &Whitted, &settings, &aovs);
+
+        assert_eq!(render(&scene, &Whitted, &settings), image);
+        for (k, pixel) in image.pixels().enumerate() {
+            let direct = layers[3].pixels().nth(k).unwrap();
+            let indirect = layers[4].pixels().nth(k).unwrap();
+            for channel in 0..3 {
+                assert!((direct[channel] + indirect[channel] - pixel[channel]).abs() < 1e-5);
+            }
+        }
+
+        // Geometric buffers come from the ray through the pixel center, like a single sample
+        assert_eq!(
+            render(&scene, &Depth, &RenderSettings::default()),
+            layers[0]
+        );
+
+        // Both spheres are glass, one around the center and the other to the upper right
+        let center = (16, 12);
+        let second = (22, 8);
+        assert_ne!(
+            layers[1].get_pixel(center.0, center.1),
+            layers[1].get_pixel(second.0, second.1)
+        );
+        assert_eq!(
+            layers[2].get_pixel(center.0, center.1),
+            layers[2].get_pixel(second.0, second.1)
+        );
+        assert_eq!(&Rgb([0., 0., 0.]), layers[2].get_pixel(0, 23));
+
+        // For 8-bit formats depths span the whole range and normals are shifted out of negatives
+        let depth = Aov::Depth.to_unit_range(&layers[0]);
+        let depths = || depth.pixels().map(|pixel| pixel[0]);
+        assert_eq!(0., depths().fold(f32::INFINITY, f32::min));
+        assert_eq!(1., depths().fold(f32::NEG_INFINITY, f32::max));
+        let normals = ImageBuffer::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgb([-1., 0., 1.])
+            } else {
+                Rgb([0.5, -0.5, 0.])
+            }
+        });
+        let normals = Aov::Normal.to_unit_range(&normals);
+        assert_eq!(&Rgb([0., 0.5, 1.]), normals.get_pixel(0, 0));
+        assert_eq!(&Rgb([0.75, 0.25, 0.5]), normals.get_pixel(1, 0));
+        assert!(output::is_hdr("renders/out.depth.EXR") && !output::is_hdr("out.depth.png"));
+
+        assert_eq!(
+            Path::new("renders/out.depth.exr"),
+            Aov::Depth.path("renders/out.exr")
+        );
+    }
 }
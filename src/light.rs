@@ -1,12 +1,216 @@
+use std::f64::consts::PI;
+
+use serde::Deserialize;
+
 use crate::objects::RayIntersect;
-use crate::vector::Norm;
-use crate::{intersect_objects, Normalize, Scene, Vec3};
+use crate::sampling::{orthonormal_basis, stratified, uniform_disk, uniform_sphere, Rng};
+use crate::vector::{Cross, Norm};
+use crate::{ambient_occlusion, transmission, Normalize, Scene, Vec3};
+
+// How light intensity decreases with distance from the light
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Attenuation {
+    #[default]
+    None,
+    InverseSquare,
+    // 1 / (constant + linear * d + quadratic * d^2)
+    Polynomial {
+        constant: f64,
+        linear: f64,
+        quadratic: f64,
+    },
+}
+
+impl Attenuation {
+    pub fn factor(&self, distance: f64) -> f64 {
+        match *self {
+            Attenuation::None => 1.,
+            Attenuation::InverseSquare => 1. / (distance * distance).max(f64::EPSILON),
+            Attenuation::Polynomial {
+                constant,
+                linear,
+                quadratic,
+            } => {
+                1. / (constant + linear * distance + quadratic * distance * distance)
+                    .max(f64::EPSILON)
+            }
+        }
+    }
+}
+
+// Direction towards a light as seen from a point and the radiance arriving from it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LightSample {
+    pub dir: Vec3<f64>,
+    pub dist: f64,
+    pub radiance: Vec3<f64>,
+    pub pdf: Option<f64>, // Solid angle density, None for lights a random ray can't hit
+}
+
+pub trait LightIntensity: Send + Sync {
+    fn get_diffuse_light_intensity(&self, point: Vec3<f64>, norm_dir: Vec3<f64>) -> f64;
+    fn get_specular_light_intensity(
+        &self,
+        point: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        specular_exponent: f64,
+        ray_dir: Vec3<f64>,
+    ) -> f64;
+
+    // Fraction of the light that reaches the point on a surface facing norm_dir per color
+    // channel, from 0 in full shadow to 1 when fully lit. Transparent occluders let a tinted
+    // part of the light through, shadow rays are continued ray_offset past each of them
+    fn transmission(
+        &self,
+        point: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        scene: &Scene,
+        ray_offset: f64,
+        rng: &mut Rng,
+    ) -> Vec3<f64>;
+
+    fn get_light_color(&self) -> Vec3<f64>;
+
+    // Picks a direction towards the light for the path tracer. Point, spot and directional
+    // lights are scaled by pi so that diffuse surfaces are as bright as with Whitted tracing,
+    // while area lights emit their intensity as radiance from both sides and attenuate only
+    // with distance as physics dictates
+    fn sample(&self, point: Vec3<f64>, rng: &mut Rng) -> Option<LightSample>;
+
+    // Where a ray runs into the light, if it has a surface or surrounds the scene
+    fn hit(&self, _origin: Vec3<f64>, _dir: Vec3<f64>) -> Option<LightSample> {
+        None
+    }
+
+    // Radiance arriving evenly from all directions, for lights that can't be sampled
+    fn ambient(&self) -> Option<Vec3<f64>> {
+        None
+    }
+}
+
+fn delta_sample(light_pos: Vec3<f64>, point: Vec3<f64>, radiance: Vec3<f64>) -> LightSample {
+    LightSample {
+        dir: (light_pos - point).normalize(),
+        dist: (light_pos - point).norm(),
+        radiance: radiance * PI,
+        pdf: None,
+    }
+}
+
+// Sample of a flat light with the given area, converting the area density to solid angle
+fn flat_sample(
+    light_point: Vec3<f64>,
+    norm: Vec3<f64>,
+    area: f64,
+    point: Vec3<f64>,
+    radiance: Vec3<f64>,
+) -> Option<LightSample> {
+    let dist = (light_point - point).norm();
+    let dir = (light_point - point).normalize();
+    let cos_light = (dir * norm).abs();
+    if cos_light < f64::EPSILON {
+        return None;
+    }
+
+    Some(LightSample {
+        dir,
+        dist,
+        radiance,
+        pdf: Some(dist * dist / (area * cos_light)),
+    })
+}
+
+// Samples an emissive object of the scene like an area light. The sample is ray_offset closer than
+// the surface, so that shadow rays towards it aren't blocked by the object itself
+pub fn sample_emitter(
+    scene: &Scene,
+    object_id: usize,
+    point: Vec3<f64>,
+    ray_offset: f64,
+    rng: &mut Rng,
+) -> Option<LightSample> {
+    let obj = &scene.objects()[object_id];
+    let surface = obj.sample_surface(rng.next_f64(), rng.next_f64());
+    let radiance = scene
+        .material_at(object_id, surface.uv, surface.point)
+        .emission();
+
+    flat_sample(surface.point, surface.norm_dir, obj.area(), point, radiance).map(|sample| {
+        LightSample {
+            dist: (sample.dist - ray_offset).max(0.),
+            ..sample
+        }
+    })
+}
+
+// Solid angle density of sample_emitter picking the point a ray hits after dist
+pub fn emitter_pdf(obj: &dyn RayIntersect, dir: Vec3<f64>, dist: f64, norm_dir: Vec3<f64>) -> f64 {
+    let cos_light = (dir * norm_dir).abs().max(f64::EPSILON);
+    dist * dist / (obj.area() * cos_light)
+}
+
+fn diffuse_intensity(point_to_light: Vec3<f64>, intensity: f64, norm: Vec3<f64>) -> f64 {
+    intensity * (point_to_light * norm).max(0.) // Dot product of normalized vectors gives cos of desired angle that represents the final light intensity
+}
+
+fn specular_intensity(
+    point_to_light: Vec3<f64>,
+    intensity: f64,
+    norm_dir: Vec3<f64>,
+    specular_exponent: f64,
+    ray_dir: Vec3<f64>,
+) -> f64 {
+    f64::powf(
+        (crate::objects::reflect(point_to_light, norm_dir) * ray_dir).max(0.),
+        specular_exponent,
+    ) * intensity
+}
+
+fn transmission_from(
+    light_pos: Vec3<f64>,
+    point: Vec3<f64>,
+    scene: &Scene,
+    ray_offset: f64,
+) -> Vec3<f64> {
+    transmission(
+        point,
+        (light_pos - point).normalize(),
+        (light_pos - point).norm(),
+        scene,
+        ray_offset,
+    )
+}
+
+// Shoots a shadow ray to each of the stratified samples of the light surface. The unit square
+// is mapped onto the surface by to_surface
+fn area_transmission<F>(
+    samples: usize,
+    point: Vec3<f64>,
+    scene: &Scene,
+    ray_offset: f64,
+    rng: &mut Rng,
+    to_surface: F,
+) -> Vec3<f64>
+where
+    F: Fn(f64, f64) -> Vec3<f64>,
+{
+    let samples = stratified(samples, rng);
+    let total = samples
+        .iter()
+        .fold(Vec3::new(0., 0., 0.), |total, &(u, v)| {
+            total + transmission_from(to_surface(u, v), point, scene, ray_offset)
+        });
+
+    total * (1. / samples.len() as f64)
+}
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Point {
     position: Vec3<f64>,
     intensity: f64,
     color: Vec3<f64>,
+    attenuation: Attenuation,
 }
 
 impl Point {
@@ -15,55 +219,709 @@ impl Point {
             position,
             intensity,
             color,
+            attenuation: Attenuation::None,
+        }
+    }
+
+    pub fn with_attenuation(self, attenuation: Attenuation) -> Self {
+        Self {
+            attenuation,
+            ..self
         }
     }
+
+    fn intensity_at(&self, point: Vec3<f64>) -> f64 {
+        self.intensity * self.attenuation.factor((self.position - point).norm())
+    }
 }
 
-pub trait LightIntensity {
-    fn get_diffuse_light_intensity(&self, point: Vec3<f64>, norm_dir: Vec3<f64>) -> f64;
+impl LightIntensity for Point {
+    fn get_diffuse_light_intensity(&self, point: Vec3<f64>, norm: Vec3<f64>) -> f64 {
+        diffuse_intensity(
+            (self.position - point).normalize(),
+            self.intensity_at(point),
+            norm,
+        )
+    }
+
     fn get_specular_light_intensity(
         &self,
         point: Vec3<f64>,
         norm_dir: Vec3<f64>,
-        obj: &dyn RayIntersect,
+        specular_exponent: f64,
         ray_dir: Vec3<f64>,
-    ) -> f64;
+    ) -> f64 {
+        specular_intensity(
+            (self.position - point).normalize(),
+            self.intensity_at(point),
+            norm_dir,
+            specular_exponent,
+            ray_dir,
+        )
+    }
+
+    fn transmission(
+        &self,
+        point: Vec3<f64>,
+        _norm_dir: Vec3<f64>,
+        scene: &Scene,
+        ray_offset: f64,
+        _rng: &mut Rng,
+    ) -> Vec3<f64> {
+        transmission_from(self.position, point, scene, ray_offset)
+    }
 
-    fn is_in_shadow(&self, point: Vec3<f64>, scene: &Scene) -> bool;
+    fn get_light_color(&self) -> Vec3<f64> {
+        self.color
+    }
 
-    fn get_light_color(&self) -> Vec3<f64>;
+    fn sample(&self, point: Vec3<f64>, _rng: &mut Rng) -> Option<LightSample> {
+        Some(delta_sample(
+            self.position,
+            point,
+            self.color * self.intensity_at(point),
+        ))
+    }
 }
 
-impl LightIntensity for Point {
+// Parallelogram spanned by two edges around its center
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rectangle {
+    center: Vec3<f64>,
+    edge_a: Vec3<f64>,
+    edge_b: Vec3<f64>,
+    intensity: f64,
+    color: Vec3<f64>,
+    attenuation: Attenuation,
+    samples: usize, // Shadow rays per shaded point
+}
+
+impl Rectangle {
+    pub fn new(
+        center: Vec3<f64>,
+        edge_a: Vec3<f64>,
+        edge_b: Vec3<f64>,
+        intensity: f64,
+        color: Vec3<f64>,
+        samples: usize,
+    ) -> Self {
+        assert!(samples > 0, "area lights need at least one sample");
+        assert!(
+            edge_a.cross(edge_b).norm() > 0.,
+            "rectangle edges must not be zero or parallel"
+        );
+
+        Self {
+            center,
+            edge_a,
+            edge_b,
+            intensity,
+            color,
+            attenuation: Attenuation::None,
+            samples,
+        }
+    }
+
+    pub fn with_attenuation(self, attenuation: Attenuation) -> Self {
+        Self {
+            attenuation,
+            ..self
+        }
+    }
+
+    fn intensity_at(&self, point: Vec3<f64>) -> f64 {
+        self.intensity * self.attenuation.factor((self.center - point).norm())
+    }
+}
+
+impl LightIntensity for Rectangle {
+    fn get_diffuse_light_intensity(&self, point: Vec3<f64>, norm: Vec3<f64>) -> f64 {
+        diffuse_intensity(
+            (self.center - point).normalize(),
+            self.intensity_at(point),
+            norm,
+        )
+    }
+
+    fn get_specular_light_intensity(
+        &self,
+        point: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        specular_exponent: f64,
+        ray_dir: Vec3<f64>,
+    ) -> f64 {
+        specular_intensity(
+            (self.center - point).normalize(),
+            self.intensity_at(point),
+            norm_dir,
+            specular_exponent,
+            ray_dir,
+        )
+    }
+
+    fn transmission(
+        &self,
+        point: Vec3<f64>,
+        _norm_dir: Vec3<f64>,
+        scene: &Scene,
+        ray_offset: f64,
+        rng: &mut Rng,
+    ) -> Vec3<f64> {
+        area_transmission(self.samples, point, scene, ray_offset, rng, |u, v| {
+            self.center + self.edge_a * (u - 0.5) + self.edge_b * (v - 0.5)
+        })
+    }
+
+    fn get_light_color(&self) -> Vec3<f64> {
+        self.color
+    }
+
+    fn sample(&self, point: Vec3<f64>, rng: &mut Rng) -> Option<LightSample> {
+        let light_point = self.center
+            + self.edge_a * (rng.next_f64() - 0.5)
+            + self.edge_b * (rng.next_f64() - 0.5);
+        flat_sample(
+            light_point,
+            self.edge_a.cross(self.edge_b).normalize(),
+            self.edge_a.cross(self.edge_b).norm(),
+            point,
+            self.color * self.intensity,
+        )
+    }
+
+    fn hit(&self, origin: Vec3<f64>, dir: Vec3<f64>) -> Option<LightSample> {
+        let norm = self.edge_a.cross(self.edge_b);
+        let denom = dir * norm;
+        if denom.abs() < f64::EPSILON {
+            return None;
+        }
+        let dist = (self.center - origin) * norm / denom;
+        if dist <= 0. {
+            return None;
+        }
+
+        // Coordinates of the hit point along the edges
+        let local = origin + dir * dist - self.center;
+        let area_squared = norm * norm;
+        let u = local.cross(self.edge_b) * norm / area_squared;
+        let v = self.edge_a.cross(local) * norm / area_squared;
+        if u.abs() > 0.5 || v.abs() > 0.5 {
+            return None;
+        }
+
+        flat_sample(
+            origin + dir * dist,
+            norm.normalize(),
+            norm.norm(),
+            origin,
+            self.color * self.intensity,
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Disk {
+    center: Vec3<f64>,
+    radius: f64,
+    tangent: Vec3<f64>, // Together with the bitangent spans the plane of the disk
+    bitangent: Vec3<f64>,
+    intensity: f64,
+    color: Vec3<f64>,
+    attenuation: Attenuation,
+    samples: usize,
+}
+
+impl Disk {
+    pub fn new(
+        center: Vec3<f64>,
+        norm: Vec3<f64>,
+        radius: f64,
+        intensity: f64,
+        color: Vec3<f64>,
+        samples: usize,
+    ) -> Self {
+        assert!(samples > 0, "area lights need at least one sample");
+        let (tangent, bitangent) = orthonormal_basis(norm.normalize());
+
+        Self {
+            center,
+            radius,
+            tangent,
+            bitangent,
+            intensity,
+            color,
+            attenuation: Attenuation::None,
+            samples,
+        }
+    }
+
+    pub fn with_attenuation(self, attenuation: Attenuation) -> Self {
+        Self {
+            attenuation,
+            ..self
+        }
+    }
+
+    fn intensity_at(&self, point: Vec3<f64>) -> f64 {
+        self.intensity * self.attenuation.factor((self.center - point).norm())
+    }
+}
+
+impl LightIntensity for Disk {
+    fn get_diffuse_light_intensity(&self, point: Vec3<f64>, norm: Vec3<f64>) -> f64 {
+        diffuse_intensity(
+            (self.center - point).normalize(),
+            self.intensity_at(point),
+            norm,
+        )
+    }
+
+    fn get_specular_light_intensity(
+        &self,
+        point: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        specular_exponent: f64,
+        ray_dir: Vec3<f64>,
+    ) -> f64 {
+        specular_intensity(
+            (self.center - point).normalize(),
+            self.intensity_at(point),
+            norm_dir,
+            specular_exponent,
+            ray_dir,
+        )
+    }
+
+    fn transmission(
+        &self,
+        point: Vec3<f64>,
+        _norm_dir: Vec3<f64>,
+        scene: &Scene,
+        ray_offset: f64,
+        rng: &mut Rng,
+    ) -> Vec3<f64> {
+        area_transmission(self.samples, point, scene, ray_offset, rng, |u, v| {
+            let (x, y) = uniform_disk(u, v);
+            self.center + (self.tangent * x + self.bitangent * y) * self.radius
+        })
+    }
+
+    fn get_light_color(&self) -> Vec3<f64> {
+        self.color
+    }
+
+    fn sample(&self, point: Vec3<f64>, rng: &mut Rng) -> Option<LightSample> {
+        let (x, y) = uniform_disk(rng.next_f64(), rng.next_f64());
+        flat_sample(
+            self.center + (self.tangent * x + self.bitangent * y) * self.radius,
+            self.tangent.cross(self.bitangent),
+            PI * self.radius * self.radius,
+            point,
+            self.color * self.intensity,
+        )
+    }
+
+    fn hit(&self, origin: Vec3<f64>, dir: Vec3<f64>) -> Option<LightSample> {
+        let norm = self.tangent.cross(self.bitangent);
+        let denom = dir * norm;
+        if denom.abs() < f64::EPSILON {
+            return None;
+        }
+        let dist = (self.center - origin) * norm / denom;
+        if dist <= 0. || (origin + dir * dist - self.center).norm() > self.radius {
+            return None;
+        }
+
+        flat_sample(
+            origin + dir * dist,
+            norm,
+            PI * self.radius * self.radius,
+            origin,
+            self.color * self.intensity,
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sphere {
+    center: Vec3<f64>,
+    radius: f64,
+    intensity: f64,
+    color: Vec3<f64>,
+    attenuation: Attenuation,
+    samples: usize,
+}
+
+impl Sphere {
+    pub fn new(
+        center: Vec3<f64>,
+        radius: f64,
+        intensity: f64,
+        color: Vec3<f64>,
+        samples: usize,
+    ) -> Self {
+        assert!(samples > 0, "area lights need at least one sample");
+
+        Self {
+            center,
+            radius,
+            intensity,
+            color,
+            attenuation: Attenuation::None,
+            samples,
+        }
+    }
+
+    pub fn with_attenuation(self, attenuation: Attenuation) -> Self {
+        Self {
+            attenuation,
+            ..self
+        }
+    }
+
+    fn intensity_at(&self, point: Vec3<f64>) -> f64 {
+        self.intensity * self.attenuation.factor((self.center - point).norm())
+    }
+
+    // Cosine of the half-angle the sphere spans seen from a point outside of it
+    fn cos_cone_angle(&self, point: Vec3<f64>) -> Option<f64> {
+        let dist = (self.center - point).norm();
+        if dist <= self.radius {
+            return None;
+        }
+        let sin_max = self.radius / dist;
+        Some((1. - sin_max * sin_max).sqrt())
+    }
+}
+
+impl LightIntensity for Sphere {
     fn get_diffuse_light_intensity(&self, point: Vec3<f64>, norm: Vec3<f64>) -> f64 {
-        let point_to_light = (self.position - point).normalize();
+        diffuse_intensity(
+            (self.center - point).normalize(),
+            self.intensity_at(point),
+            norm,
+        )
+    }
+
+    fn get_specular_light_intensity(
+        &self,
+        point: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        specular_exponent: f64,
+        ray_dir: Vec3<f64>,
+    ) -> f64 {
+        specular_intensity(
+            (self.center - point).normalize(),
+            self.intensity_at(point),
+            norm_dir,
+            specular_exponent,
+            ray_dir,
+        )
+    }
+
+    fn transmission(
+        &self,
+        point: Vec3<f64>,
+        _norm_dir: Vec3<f64>,
+        scene: &Scene,
+        ray_offset: f64,
+        rng: &mut Rng,
+    ) -> Vec3<f64> {
+        area_transmission(self.samples, point, scene, ray_offset, rng, |u, v| {
+            self.center + uniform_sphere(u, v) * self.radius
+        })
+    }
+
+    fn get_light_color(&self) -> Vec3<f64> {
+        self.color
+    }
+
+    // Samples the cone of directions the sphere covers, so every sample is on its visible side
+    fn sample(&self, point: Vec3<f64>, rng: &mut Rng) -> Option<LightSample> {
+        let to_center = self.center - point;
+        let cos_max = self.cos_cone_angle(point)?;
+
+        let cos_angle = 1. - rng.next_f64() * (1. - cos_max);
+        let sin_angle = (1. - cos_angle * cos_angle).max(0.).sqrt();
+        let rotation = 2. * PI * rng.next_f64();
+        let axis = to_center.normalize();
+        let (tangent, bitangent) = orthonormal_basis(axis);
+        let dir =
+            axis * cos_angle + (tangent * rotation.cos() + bitangent * rotation.sin()) * sin_angle;
+
+        self.hit(point, dir)
+    }
+
+    fn hit(&self, origin: Vec3<f64>, dir: Vec3<f64>) -> Option<LightSample> {
+        let cos_max = self.cos_cone_angle(origin)?;
+
+        let to_center = self.center - origin;
+        let projection = to_center * dir;
+        let distance_squared = to_center * to_center - projection * projection;
+        if projection <= 0. || distance_squared > self.radius * self.radius {
+            return None;
+        }
+
+        Some(LightSample {
+            dir,
+            dist: projection - (self.radius * self.radius - distance_squared).sqrt(),
+            radiance: self.color * self.intensity,
+            pdf: Some(1. / (2. * PI * (1. - cos_max))),
+        })
+    }
+}
+
+// Infinitely far away light like the sun, all of its rays are parallel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Directional {
+    dir: Vec3<f64>, // Direction the light travels in
+    intensity: f64,
+    color: Vec3<f64>,
+}
+
+impl Directional {
+    pub fn new(dir: Vec3<f64>, intensity: f64, color: Vec3<f64>) -> Self {
+        Self {
+            dir: dir.normalize(),
+            intensity,
+            color,
+        }
+    }
+}
+
+impl LightIntensity for Directional {
+    fn get_diffuse_light_intensity(&self, _point: Vec3<f64>, norm: Vec3<f64>) -> f64 {
+        diffuse_intensity(-self.dir, self.intensity, norm)
+    }
+
+    fn get_specular_light_intensity(
+        &self,
+        _point: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        specular_exponent: f64,
+        ray_dir: Vec3<f64>,
+    ) -> f64 {
+        specular_intensity(
+            -self.dir,
+            self.intensity,
+            norm_dir,
+            specular_exponent,
+            ray_dir,
+        )
+    }
+
+    fn transmission(
+        &self,
+        point: Vec3<f64>,
+        _norm_dir: Vec3<f64>,
+        scene: &Scene,
+        ray_offset: f64,
+        _rng: &mut Rng,
+    ) -> Vec3<f64> {
+        transmission(point, -self.dir, f64::INFINITY, scene, ray_offset)
+    }
+
+    fn get_light_color(&self) -> Vec3<f64> {
+        self.color
+    }
+
+    fn sample(&self, _point: Vec3<f64>, _rng: &mut Rng) -> Option<LightSample> {
+        Some(LightSample {
+            dir: -self.dir,
+            dist: f64::INFINITY,
+            radiance: self.color * (self.intensity * PI),
+            pdf: None,
+        })
+    }
+}
+
+// Point light restricted to a cone. Intensity fades out over the falloff angle towards the
+// edge of the cone
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Spot {
+    position: Vec3<f64>,
+    dir: Vec3<f64>,
+    cos_cone_angle: f64, // Cone half-angle
+    cos_full_intensity_angle: f64,
+    intensity: f64,
+    color: Vec3<f64>,
+    attenuation: Attenuation,
+}
+
+impl Spot {
+    pub fn new(
+        position: Vec3<f64>,
+        dir: Vec3<f64>,
+        cone_angle: f64,
+        falloff: f64,
+        intensity: f64,
+        color: Vec3<f64>,
+    ) -> Self {
+        Self {
+            position,
+            dir: dir.normalize(),
+            cos_cone_angle: cone_angle.cos(),
+            cos_full_intensity_angle: (cone_angle - falloff).max(0.).cos(),
+            intensity,
+            color,
+            attenuation: Attenuation::None,
+        }
+    }
+
+    fn intensity_at(&self, point: Vec3<f64>) -> f64 {
+        let intensity = self.intensity * self.attenuation.factor((self.position - point).norm());
+
+        let cos_angle = (point - self.position).normalize() * self.dir;
+        if cos_angle >= self.cos_full_intensity_angle {
+            return intensity;
+        }
+        if cos_angle <= self.cos_cone_angle {
+            return 0.;
+        }
 
-        self.intensity * (point_to_light * norm).max(0.) // Dot product of normalized vectors gives cos of desired angle that represents the final light intensity
+        // Smoothstep across the falloff
+        let t = (cos_angle - self.cos_cone_angle)
+            / (self.cos_full_intensity_angle - self.cos_cone_angle);
+        intensity * t * t * (3. - 2. * t)
+    }
+
+    pub fn with_attenuation(self, attenuation: Attenuation) -> Self {
+        Self {
+            attenuation,
+            ..self
+        }
+    }
+}
+
+impl LightIntensity for Spot {
+    fn get_diffuse_light_intensity(&self, point: Vec3<f64>, norm: Vec3<f64>) -> f64 {
+        diffuse_intensity(
+            (self.position - point).normalize(),
+            self.intensity_at(point),
+            norm,
+        )
     }
 
     fn get_specular_light_intensity(
         &self,
         point: Vec3<f64>,
         norm_dir: Vec3<f64>,
-        obj: &dyn RayIntersect,
+        specular_exponent: f64,
         ray_dir: Vec3<f64>,
     ) -> f64 {
-        let point_to_light = (self.position - point).normalize();
+        specular_intensity(
+            (self.position - point).normalize(),
+            self.intensity_at(point),
+            norm_dir,
+            specular_exponent,
+            ray_dir,
+        )
+    }
+
+    fn transmission(
+        &self,
+        point: Vec3<f64>,
+        _norm_dir: Vec3<f64>,
+        scene: &Scene,
+        ray_offset: f64,
+        _rng: &mut Rng,
+    ) -> Vec3<f64> {
+        transmission_from(self.position, point, scene, ray_offset)
+    }
+
+    fn get_light_color(&self) -> Vec3<f64> {
+        self.color
+    }
+
+    fn sample(&self, point: Vec3<f64>, _rng: &mut Rng) -> Option<LightSample> {
+        Some(delta_sample(
+            self.position,
+            point,
+            self.color * self.intensity_at(point),
+        ))
+    }
+}
 
-        f64::powf(
-            (crate::objects::reflect(point_to_light, norm_dir) * ray_dir).max(0.),
-            obj.get_material().specular_exponent,
-        ) * self.intensity
+// Uniform light coming from everywhere, so that surfaces in full shadow are not pitch black
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ambient {
+    intensity: f64,
+    color: Vec3<f64>,
+    occlusion: Option<(usize, f64)>, // Samples and max distance of ambient occlusion
+}
+
+impl Ambient {
+    pub fn new(intensity: f64, color: Vec3<f64>) -> Self {
+        Self {
+            intensity,
+            color,
+            occlusion: None,
+        }
     }
 
-    fn is_in_shadow(&self, point: Vec3<f64>, scene: &Scene) -> bool {
-        match intersect_objects(point, (self.position - point).normalize(), scene) {
-            None => false,
-            Some((_, props)) => props.ray_length < (self.position - point).norm(),
+    // Darkens creases and contact points by how much of the hemisphere above them is blocked
+    // within max_distance. Only used by the Whitted integrator, paths find occluders by
+    // themselves
+    pub fn with_occlusion(self, samples: usize, max_distance: f64) -> Self {
+        assert!(samples > 0, "ambient occlusion needs at least one sample");
+
+        Self {
+            occlusion: Some((samples, max_distance)),
+            ..self
         }
     }
+}
+
+impl LightIntensity for Ambient {
+    fn get_diffuse_light_intensity(&self, _point: Vec3<f64>, _norm: Vec3<f64>) -> f64 {
+        self.intensity
+    }
+
+    fn get_specular_light_intensity(
+        &self,
+        _point: Vec3<f64>,
+        _norm_dir: Vec3<f64>,
+        _specular_exponent: f64,
+        _ray_dir: Vec3<f64>,
+    ) -> f64 {
+        0. // There is no direction to get a highlight from
+    }
+
+    fn transmission(
+        &self,
+        point: Vec3<f64>,
+        norm_dir: Vec3<f64>,
+        scene: &Scene,
+        _ray_offset: f64,
+        rng: &mut Rng,
+    ) -> Vec3<f64> {
+        let visible = match self.occlusion {
+            Some((samples, max_distance)) => {
+                ambient_occlusion(point, norm_dir, samples, max_distance, scene, rng)
+            }
+            None => 1.,
+        };
+        Vec3::new(visible, visible, visible)
+    }
 
     fn get_light_color(&self) -> Vec3<f64> {
         self.color
     }
+
+    fn sample(&self, _point: Vec3<f64>, _rng: &mut Rng) -> Option<LightSample> {
+        None // Reached by rays that leave the scene instead
+    }
+
+    fn hit(&self, _origin: Vec3<f64>, dir: Vec3<f64>) -> Option<LightSample> {
+        Some(LightSample {
+            dir,
+            dist: f64::INFINITY,
+            radiance: self.color * self.intensity,
+            pdf: None,
+        })
+    }
+
+    fn ambient(&self) -> Option<Vec3<f64>> {
+        Some(self.color * self.intensity)
+    }
 }
@@ -0,0 +1,946 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::light::{
+    self, Ambient, Attenuation, Directional, Disk, LightIntensity, Point, Rectangle, Spot,
+};
+use crate::material::{Fresnel, Material, MaterialTextures, Pbr, PbrTextures, Phong};
+use crate::objects::circular_plane::CircularPlane;
+use crate::objects::mesh::Mesh;
+use crate::objects::sphere::Sphere;
+use crate::objects::triangle::Triangle;
+use crate::objects::RayIntersect;
+use crate::scene::{Cam, Scene};
+use crate::texture::{
+    ImageTexture, Interpolation, Mapping, Pattern, Procedural, Texture, TextureId, Wrap,
+};
+use crate::vector::{Cross, Norm, Vec3};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl SceneError {
+    fn at(source: &str, offset: usize, message: String) -> Self {
+        // Lines and columns are 1-based, as text editors show them
+        let before = &source[..offset.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+
+        SceneError::Parse {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{err}"),
+            SceneError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {line}, column {column}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    camera: Spanned<CamDescription>,
+    background_color: Vec3<f64>,
+    // Sorted by name, so that the first error in them doesn't depend on hashing
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDescription>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDescription>,
+    // Metallic/roughness materials, named alongside the Phong ones
+    #[serde(default)]
+    pbr_materials: BTreeMap<Spanned<String>, PbrMaterialDescription>,
+    #[serde(default)]
+    objects: ObjectsDescription,
+    #[serde(default)]
+    lights: LightsDescription,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CamDescription {
+    width: usize,
+    height: usize,
+    fov: Spanned<f64>, // In degrees
+    #[serde(default = "origin")]
+    position: Vec3<f64>,
+    look_at: Option<Spanned<Vec3<f64>>>,
+    direction: Option<Spanned<Vec3<f64>>>,
+    up: Option<Spanned<Vec3<f64>>>, // The y axis by default
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+enum TextureDescription {
+    Image(ImageDescription),
+    Checker(CheckerDescription),
+    Noise(NoiseDescription),
+    Turbulence(TurbulenceDescription),
+    Marble(MarbleDescription),
+    Wood(WoodDescription),
+    Gradient(GradientDescription),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImageDescription {
+    path: Spanned<PathBuf>, // Relative to the scene file
+    #[serde(default)]
+    wrap: Wrap,
+    #[serde(default)]
+    interpolation: Interpolation,
+    #[serde(default = "one")]
+    scale: f64,
+    // Skips sRGB decoding of 8-bit images. Only normal and bump maps skip it by default, and they
+    // can't be decoded
+    linear: Option<Spanned<bool>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckerDescription {
+    #[serde(default = "black_and_white")]
+    colors: [Vec3<f64>; 2],
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "one")]
+    scale: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseDescription {
+    #[serde(default = "black_and_white")]
+    colors: [Vec3<f64>; 2],
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "one")]
+    scale: f64,
+    #[serde(default)]
+    seed: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TurbulenceDescription {
+    #[serde(default = "black_and_white")]
+    colors: [Vec3<f64>; 2],
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "one")]
+    scale: f64,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "default_octaves")]
+    octaves: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MarbleDescription {
+    #[serde(default = "black_and_white")]
+    colors: [Vec3<f64>; 2],
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "one")]
+    scale: f64,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "default_octaves")]
+    octaves: usize,
+    #[serde(default)]
+    distortion: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WoodDescription {
+    #[serde(default = "black_and_white")]
+    colors: [Vec3<f64>; 2],
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "one")]
+    scale: f64,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    distortion: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GradientDescription {
+    #[serde(default = "black_and_white")]
+    colors: [Vec3<f64>; 2],
+    #[serde(default)]
+    mapping: Mapping,
+    #[serde(default = "one")]
+    scale: f64,
+    direction: Option<Spanned<Vec3<f64>>>, // The x axis by default
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDescription {
+    diffuse_color: Vec3<f64>,
+    diffuse_albedo: f64,
+    specular_exponent: f64,
+    specular_albedo: f64,
+    reflective_albedo: f64,
+    refractive_index: f64,
+    refractive_albedo: f64,
+    #[serde(default)]
+    fresnel: Option<Fresnel>,
+    #[serde(default)]
+    roughness: f64,
+    #[serde(default = "black")]
+    emission: Vec3<f64>,
+    #[serde(default = "one")]
+    emission_strength: f64,
+    #[serde(default)]
+    textures: MaterialTexturesDescription,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct MaterialTexturesDescription {
+    diffuse_color: Option<Spanned<String>>,
+    diffuse_albedo: Option<Spanned<String>>,
+    specular_albedo: Option<Spanned<String>>,
+    reflective_albedo: Option<Spanned<String>>,
+    roughness: Option<Spanned<String>>,
+    emission: Option<Spanned<String>>,
+    normal: Option<Spanned<String>>,
+    height: Option<Spanned<String>>,
+    height_scale: Option<f64>, // In scene units per unit of height, 1 by default
+}
+
+// Defaults are those of glTF
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PbrMaterialDescription {
+    #[serde(default = "white")]
+    base_color: Vec3<f64>,
+    #[serde(default = "one")]
+    metallic: f64,
+    #[serde(default = "one")]
+    roughness: f64,
+    #[serde(default = "default_ior")]
+    ior: f64,
+    #[serde(default = "black")]
+    emission: Vec3<f64>,
+    #[serde(default = "one")]
+    emission_strength: f64,
+    #[serde(default)]
+    transmission: f64,
+    #[serde(default)]
+    textures: PbrTexturesDescription,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct PbrTexturesDescription {
+    base_color: Option<Spanned<String>>,
+    metallic: Option<Spanned<String>>,
+    roughness: Option<Spanned<String>>,
+    emission: Option<Spanned<String>>,
+    normal: Option<Spanned<String>>,
+    height: Option<Spanned<String>>,
+    height_scale: Option<f64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ObjectsDescription {
+    #[serde(default)]
+    sphere: Vec<SphereDescription>,
+    #[serde(default)]
+    circular_plane: Vec<CircularPlaneDescription>,
+    #[serde(default)]
+    triangle: Vec<TriangleDescription>,
+    #[serde(default)]
+    mesh: Vec<MeshDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereDescription {
+    center: Vec3<f64>,
+    radius: Spanned<f64>,
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CircularPlaneDescription {
+    center: Vec3<f64>,
+    radius: Spanned<f64>,
+    material: Spanned<String>,
+    plane_vector_a: Spanned<Vec3<f64>>,
+    plane_vector_b: Spanned<Vec3<f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriangleDescription {
+    vertices: [Vec3<f64>; 3],
+    normals: Option<[Vec3<f64>; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: Spanned<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshDescription {
+    path: Spanned<PathBuf>,    // Relative to the scene file
+    material: Spanned<String>, // For groups without a material of their own
+    #[serde(default)]
+    materials: BTreeMap<Spanned<String>, Spanned<String>>, // Overrides materials of OBJ groups
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct LightsDescription {
+    #[serde(default)]
+    point: Vec<PointDescription>,
+    #[serde(default)]
+    rectangle: Vec<RectangleDescription>,
+    #[serde(default)]
+    disk: Vec<DiskDescription>,
+    #[serde(default)]
+    sphere: Vec<SphereLightDescription>,
+    #[serde(default)]
+    directional: Vec<DirectionalDescription>,
+    #[serde(default)]
+    spot: Vec<SpotDescription>,
+    #[serde(default)]
+    ambient: Vec<AmbientDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PointDescription {
+    position: Vec3<f64>,
+    intensity: f64,
+    #[serde(default = "white")]
+    color: Vec3<f64>,
+    #[serde(default)]
+    attenuation: Attenuation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RectangleDescription {
+    center: Vec3<f64>,
+    edge_a: Spanned<Vec3<f64>>,
+    edge_b: Spanned<Vec3<f64>>,
+    intensity: f64,
+    #[serde(default = "white")]
+    color: Vec3<f64>,
+    #[serde(default = "default_samples")]
+    samples: Spanned<usize>,
+    #[serde(default)]
+    attenuation: Attenuation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiskDescription {
+    center: Vec3<f64>,
+    normal: Spanned<Vec3<f64>>,
+    radius: f64,
+    intensity: f64,
+    #[serde(default = "white")]
+    color: Vec3<f64>,
+    #[serde(default = "default_samples")]
+    samples: Spanned<usize>,
+    #[serde(default)]
+    attenuation: Attenuation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereLightDescription {
+    center: Vec3<f64>,
+    radius: f64,
+    intensity: f64,
+    #[serde(default = "white")]
+    color: Vec3<f64>,
+    #[serde(default = "default_samples")]
+    samples: Spanned<usize>,
+    #[serde(default)]
+    attenuation: Attenuation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DirectionalDescription {
+    direction: Spanned<Vec3<f64>>,
+    intensity: f64,
+    #[serde(default = "white")]
+    color: Vec3<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpotDescription {
+    position: Vec3<f64>,
+    direction: Spanned<Vec3<f64>>,
+    cone_angle: f64, // Half-angle in degrees
+    #[serde(default)]
+    falloff: f64, // In degrees
+    intensity: f64,
+    #[serde(default = "white")]
+    color: Vec3<f64>,
+    #[serde(default)]
+    attenuation: Attenuation,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AmbientDescription {
+    intensity: f64,
+    #[serde(default = "white")]
+    color: Vec3<f64>,
+    occlusion: Option<OcclusionDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OcclusionDescription {
+    distance: f64,
+    #[serde(default = "default_samples")]
+    samples: Spanned<usize>,
+}
+
+fn default_samples() -> Spanned<usize> {
+    Spanned::new(0..0, 16)
+}
+
+fn one() -> f64 {
+    1.
+}
+
+fn default_octaves() -> usize {
+    6
+}
+
+fn origin() -> Vec3<f64> {
+    Vec3::new(0., 0., 0.)
+}
+
+fn default_ior() -> f64 {
+    1.5
+}
+
+fn black() -> Vec3<f64> {
+    Vec3::new(0., 0., 0.)
+}
+
+fn white() -> Vec3<f64> {
+    Vec3::new(1., 1., 1.)
+}
+
+fn black_and_white() -> [Vec3<f64>; 2] {
+    [Vec3::new(0., 0., 0.), white()]
+}
+
+// Paths inside the scene are resolved against base_dir
+pub fn load_scene(source: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    let description: SceneDescription = toml::from_str(source).map_err(|err| {
+        SceneError::at(
+            source,
+            err.span().map_or(0, |span| span.start),
+            err.message().to_owned(),
+        )
+    })?;
+
+    // Textures holding data rather than colors
+    let surface_maps: HashSet<&str> = description
+        .materials
+        .values()
+        .flat_map(|i| [&i.textures.normal, &i.textures.height])
+        .chain(
+            description
+                .pbr_materials
+                .values()
+                .flat_map(|i| [&i.textures.normal, &i.textures.height]),
+        )
+        .flatten()
+        .map(|name| name.get_ref().as_str())
+        .collect();
+
+    let mut textures = Vec::new();
+    let mut texture_ids = HashMap::new();
+    for (name, texture) in &description.textures {
+        texture_ids.insert(name, TextureId(textures.len()));
+        let surface_map = surface_maps.contains(name.as_str());
+        textures.push(load_texture(texture, surface_map, source, base_dir)?);
+    }
+    let texture = |name: &Spanned<String>| {
+        texture_ids.get(name.get_ref()).copied().ok_or_else(|| {
+            SceneError::at(
+                source,
+                name.span().start,
+                format!("unknown texture `{}`", name.get_ref()),
+            )
+        })
+    };
+
+    let mut materials = HashMap::new();
+    for (name, i) in &description.materials {
+        let texture = |name: &Option<Spanned<String>>| name.as_ref().map(texture).transpose();
+        materials.insert(
+            name.as_str(),
+            Material::Phong(Phong {
+                diffuse_color: i.diffuse_color,
+                diffuse_albedo: i.diffuse_albedo,
+                specular_exponent: i.specular_exponent,
+                specular_albedo: i.specular_albedo,
+                reflective_albedo: i.reflective_albedo,
+                refractive_index: i.refractive_index,
+                refractive_albedo: i.refractive_albedo,
+                fresnel: i.fresnel,
+                roughness: i.roughness,
+                emission: i.emission,
+                emission_strength: i.emission_strength,
+                textures: MaterialTextures {
+                    diffuse_color: texture(&i.textures.diffuse_color)?,
+                    diffuse_albedo: texture(&i.textures.diffuse_albedo)?,
+                    specular_albedo: texture(&i.textures.specular_albedo)?,
+                    reflective_albedo: texture(&i.textures.reflective_albedo)?,
+                    roughness: texture(&i.textures.roughness)?,
+                    emission: texture(&i.textures.emission)?,
+                    normal: texture(&i.textures.normal)?,
+                    height: texture(&i.textures.height)?
+                        .map(|id| (id, i.textures.height_scale.unwrap_or(1.))),
+                },
+            }),
+        );
+    }
+    for (name, i) in &description.pbr_materials {
+        let texture = |name: &Option<Spanned<String>>| name.as_ref().map(texture).transpose();
+        let pbr = Material::Pbr(Pbr {
+            base_color: i.base_color,
+            metallic: i.metallic,
+            roughness: i.roughness,
+            ior: i.ior,
+            emission: i.emission,
+            emission_strength: i.emission_strength,
+            transmission: i.transmission,
+            textures: PbrTextures {
+                base_color: texture(&i.textures.base_color)?,
+                metallic: texture(&i.textures.metallic)?,
+                roughness: texture(&i.textures.roughness)?,
+                emission: texture(&i.textures.emission)?,
+                normal: texture(&i.textures.normal)?,
+                height: texture(&i.textures.height)?
+                    .map(|id| (id, i.textures.height_scale.unwrap_or(1.))),
+            },
+        });
+        if materials.insert(name.get_ref().as_str(), pbr).is_some() {
+            return Err(SceneError::at(
+                source,
+                name.span().start,
+                format!("material `{}` is defined twice", name.get_ref()),
+            ));
+        }
+    }
+
+    let material = |name: &Spanned<String>| {
+        materials
+            .get(name.get_ref().as_str())
+            .copied()
+            .ok_or_else(|| {
+                SceneError::at(
+                    source,
+                    name.span().start,
+                    format!("unknown material `{}`", name.get_ref()),
+                )
+            })
+    };
+
+    let cam = load_cam(&description.camera, source)?;
+
+    let mut objects: Vec<Box<dyn RayIntersect>> = Vec::new();
+    for i in &description.objects.sphere {
+        objects.push(Box::new(Sphere::new(
+            i.center,
+            positive(&i.radius, source)?,
+            material(&i.material)?,
+        )));
+    }
+    for i in &description.objects.circular_plane {
+        let plane_vector_a = non_zero(&i.plane_vector_a, source)?;
+        let plane_vector_b = non_zero(&i.plane_vector_b, source)?;
+        if plane_vector_a.cross(plane_vector_b).norm() == 0. {
+            return Err(SceneError::at(
+                source,
+                i.plane_vector_b.span().start,
+                "plane vectors must not be parallel".to_owned(),
+            ));
+        }
+
+        objects.push(Box::new(CircularPlane::new(
+            i.center,
+            positive(&i.radius, source)?,
+            material(&i.material)?,
+            plane_vector_a,
+            plane_vector_b,
+        )));
+    }
+
+    for i in &description.objects.triangle {
+        let material = material(&i.material)?;
+        let mut triangle = match i.normals {
+            Some(normals) => Triangle::with_normals(i.vertices, normals, material),
+            None => Triangle::new(i.vertices, material),
+        };
+        if let Some(uvs) = i.uvs {
+            triangle = triangle.with_uvs(uvs);
+        }
+        objects.push(Box::new(triangle));
+    }
+    let mut meshes = Vec::new();
+    for i in &description.objects.mesh {
+        let group_materials = i
+            .materials
+            .iter()
+            .map(|(group, name)| Ok((group.get_ref().clone(), material(name)?)))
+            .collect::<Result<HashMap<_, _>, SceneError>>()?;
+
+        let mesh = Mesh::from_obj(
+            base_dir.join(i.path.get_ref()),
+            material(&i.material)?,
+            &group_materials,
+        )
+        .map_err(|err| {
+            SceneError::at(
+                source,
+                i.path.span().start,
+                format!("failed to load `{}`: {err}", i.path.get_ref().display()),
+            )
+        })?;
+        if let Some(group) = i
+            .materials
+            .keys()
+            .find(|i| !mesh.groups().contains(i.get_ref()))
+        {
+            return Err(SceneError::at(
+                source,
+                group.span().start,
+                format!(
+                    "`{}` has no group named `{}`",
+                    i.path.get_ref().display(),
+                    group.get_ref()
+                ),
+            ));
+        }
+        let start = objects.len();
+        objects.extend(mesh.into_objects());
+        meshes.push(start..objects.len());
+    }
+
+    let mut lights: Vec<Box<dyn LightIntensity>> = Vec::new();
+    for i in &description.lights.point {
+        lights.push(Box::new(
+            Point::new(i.position, i.intensity, i.color).with_attenuation(i.attenuation),
+        ));
+    }
+    for i in &description.lights.rectangle {
+        let edge_a = non_zero(&i.edge_a, source)?;
+        let edge_b = non_zero(&i.edge_b, source)?;
+        if edge_a.cross(edge_b).norm() == 0. {
+            return Err(SceneError::at(
+                source,
+                i.edge_b.span().start,
+                "rectangle edges must not be parallel".to_owned(),
+            ));
+        }
+        lights.push(Box::new(
+            Rectangle::new(
+                i.center,
+                edge_a,
+                edge_b,
+                i.intensity,
+                i.color,
+                positive(&i.samples, source)?,
+            )
+            .with_attenuation(i.attenuation),
+        ));
+    }
+    for i in &description.lights.disk {
+        lights.push(Box::new(
+            Disk::new(
+                i.center,
+                non_zero(&i.normal, source)?,
+                i.radius,
+                i.intensity,
+                i.color,
+                positive(&i.samples, source)?,
+            )
+            .with_attenuation(i.attenuation),
+        ));
+    }
+    for i in &description.lights.sphere {
+        lights.push(Box::new(
+            light::Sphere::new(
+                i.center,
+                i.radius,
+                i.intensity,
+                i.color,
+                positive(&i.samples, source)?,
+            )
+            .with_attenuation(i.attenuation),
+        ));
+    }
+    for i in &description.lights.directional {
+        lights.push(Box::new(Directional::new(
+            non_zero(&i.direction, source)?,
+            i.intensity,
+            i.color,
+        )));
+    }
+    for i in &description.lights.spot {
+        lights.push(Box::new(
+            Spot::new(
+                i.position,
+                non_zero(&i.direction, source)?,
+                i.cone_angle.to_radians(),
+                i.falloff.to_radians(),
+                i.intensity,
+                i.color,
+            )
+            .with_attenuation(i.attenuation),
+        ));
+    }
+    for i in &description.lights.ambient {
+        let mut ambient = Ambient::new(i.intensity, i.color);
+        if let Some(occlusion) = &i.occlusion {
+            ambient =
+                ambient.with_occlusion(positive(&occlusion.samples, source)?, occlusion.distance);
+        }
+        lights.push(Box::new(ambient));
+    }
+
+    Ok(
+        Scene::new(cam, description.background_color, objects, lights)
+            .with_textures(textures)
+            .with_meshes(&meshes),
+    )
+}
+
+// Surface maps are textures used as normal or bump maps
+fn load_texture(
+    texture: &TextureDescription,
+    surface_map: bool,
+    source: &str,
+    base_dir: &Path,
+) -> Result<Box<dyn Texture>, SceneError> {
+    let procedural = match texture {
+        TextureDescription::Image(i) => {
+            let linear = match &i.linear {
+                Some(linear) if surface_map && !linear.get_ref() => {
+                    return Err(SceneError::at(
+                        source,
+                        linear.span().start,
+                        "normal and bump maps must be linear".to_owned(),
+                    ))
+                }
+                Some(linear) => *linear.get_ref(),
+                None => surface_map,
+            };
+            let path = base_dir.join(i.path.get_ref());
+            let image = if linear {
+                ImageTexture::open_linear(path)
+            } else {
+                ImageTexture::open(path)
+            };
+            let image = image.map_err(|err| {
+                SceneError::at(
+                    source,
+                    i.path.span().start,
+                    format!("failed to load `{}`: {err}", i.path.get_ref().display()),
+                )
+            })?;
+            return Ok(Box::new(
+                image
+                    .with_wrap(i.wrap)
+                    .with_interpolation(i.interpolation)
+                    .with_scale(i.scale),
+            ));
+        }
+        TextureDescription::Checker(i) => Procedural::new(Pattern::Checker, i.colors)
+            .with_mapping(i.mapping)
+            .with_scale(i.scale),
+        TextureDescription::Noise(i) => Procedural::new(Pattern::Noise, i.colors)
+            .with_mapping(i.mapping)
+            .with_scale(i.scale)
+            .with_seed(i.seed),
+        TextureDescription::Turbulence(i) => {
+            Procedural::new(Pattern::Turbulence { octaves: i.octaves }, i.colors)
+                .with_mapping(i.mapping)
+                .with_scale(i.scale)
+                .with_seed(i.seed)
+        }
+        TextureDescription::Marble(i) => Procedural::new(
+            Pattern::Marble {
+                octaves: i.octaves,
+                distortion: i.distortion,
+            },
+            i.colors,
+        )
+        .with_mapping(i.mapping)
+        .with_scale(i.scale)
+        .with_seed(i.seed),
+        TextureDescription::Wood(i) => Procedural::new(
+            Pattern::Wood {
+                distortion: i.distortion,
+            },
+            i.colors,
+        )
+        .with_mapping(i.mapping)
+        .with_scale(i.scale)
+        .with_seed(i.seed),
+        TextureDescription::Gradient(i) => Procedural::new(
+            Pattern::Gradient {
+                direction: match &i.direction {
+                    Some(direction) => non_zero(direction, source)?,
+                    None => Vec3::new(1., 0., 0.),
+                },
+            },
+            i.colors,
+        )
+        .with_mapping(i.mapping)
+        .with_scale(i.scale),
+    };
+
+    Ok(Box::new(procedural))
+}
+
+fn load_cam(camera: &Spanned<CamDescription>, source: &str) -> Result<Cam, SceneError> {
+    let description = camera.get_ref();
+    let up = match &description.up {
+        Some(up) => non_zero(up, source)?,
+        None => Vec3::new(0., 1., 0.),
+    };
+    let fov = *description.fov.get_ref();
+    if !(fov > 0. && fov < 180.) {
+        return Err(SceneError::at(
+            source,
+            description.fov.span().start,
+            "camera `fov` must be between 0 and 180 degrees".to_owned(),
+        ));
+    }
+
+    let dir = match (&description.look_at, &description.direction) {
+        (Some(target), None) => {
+            if *target.get_ref() == description.position {
+                return Err(SceneError::at(
+                    source,
+                    target.span().start,
+                    "camera must not look at its own position".to_owned(),
+                ));
+            }
+            *target.get_ref() - description.position
+        }
+        (None, Some(dir)) => non_zero(dir, source)?,
+        (Some(_), Some(dir)) => {
+            return Err(SceneError::at(
+                source,
+                dir.span().start,
+                "camera needs either `look_at` or `direction`, not both".to_owned(),
+            ))
+        }
+        (None, None) => {
+            return Err(SceneError::at(
+                source,
+                camera.span().start,
+                "camera needs either `look_at` or `direction`".to_owned(),
+            ))
+        }
+    };
+
+    if dir.cross(up).norm() == 0. {
+        // A default up has no place in the file, so the view direction takes the blame
+        let span = description
+            .up
+            .as_ref()
+            .or(description.look_at.as_ref())
+            .or(description.direction.as_ref())
+            .map_or(camera.span(), |vector| vector.span());
+        return Err(SceneError::at(
+            source,
+            span.start,
+            "camera `up` must not be parallel to the view direction".to_owned(),
+        ));
+    }
+
+    Ok(Cam::new(
+        description.position,
+        dir,
+        up,
+        fov.to_radians(),
+        description.width,
+        description.height,
+    ))
+}
+
+// NaN isn't ordered against zero, so it is rejected too
+fn positive<T: PartialOrd + Default + Copy>(
+    value: &Spanned<T>,
+    source: &str,
+) -> Result<T, SceneError> {
+    if value.get_ref().partial_cmp(&T::default()) == Some(Ordering::Greater) {
+        Ok(*value.get_ref())
+    } else {
+        Err(SceneError::at(
+            source,
+            value.span().start,
+            "value must be greater than zero".to_owned(),
+        ))
+    }
+}
+
+fn non_zero(vector: &Spanned<Vec3<f64>>, source: &str) -> Result<Vec3<f64>, SceneError> {
+    if vector.get_ref().norm() == 0. {
+        Err(SceneError::at(
+            source,
+            vector.span().start,
+            "vector must not be zero".to_owned(),
+        ))
+    } else {
+        Ok(*vector.get_ref())
+    }
+}
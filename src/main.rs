@@ -1,103 +1,209 @@
-use raytracer::material::Material;
-use raytracer::scene::{Cam, Scene};
-use raytracer::vector::Vec3;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+
+use raytracer::aov::Aov;
+use raytracer::filter::Filter;
+use raytracer::integrator::IntegratorKind;
+use raytracer::output::{Encoding, ToneMapOperator, ToneMapping};
+use raytracer::scene::Scene;
 use raytracer::*;
 
-fn main() {
-    let ivory = Material {
-        diffuse_color: Vec3::new(0.4, 0.4, 0.3),
-        diffuse_albedo: 0.6,
-        specular_exponent: 50.0,
-        specular_albedo: 0.3,
-        reflective_albedo: 0.1,
-        refractive_index: 1.0,
-        refractive_albedo: 0.0,
-    };
-    let red_rubber = Material {
-        diffuse_color: Vec3::new(0.3, 0.1, 0.1),
-        diffuse_albedo: 0.9,
-        specular_exponent: 10.0,
-        specular_albedo: 0.1,
-        reflective_albedo: 0.0,
-        refractive_index: 1.0,
-        refractive_albedo: 0.0,
+#[derive(Parser)]
+#[command(about = "Renders a TOML scene description into an image")]
+struct Args {
+    /// Scene description file
+    scene: PathBuf,
+
+    /// Where to save the rendered image, the format is picked by its extension
+    #[arg(short, long, default_value = "output.png")]
+    output: PathBuf,
+
+    /// Override the image width set by the scene camera
+    #[arg(long, value_parser = positive::<usize>)]
+    width: Option<usize>,
+
+    /// Override the image height set by the scene camera
+    #[arg(long, value_parser = positive::<usize>)]
+    height: Option<usize>,
+
+    /// Rendering algorithm: whitted, path for global illumination with many samples, or the
+    /// normals, depth and ao (ambient occlusion) debug views
+    #[arg(long, default_value_t = IntegratorKind::Whitted)]
+    integrator: IntegratorKind,
+
+    /// Number of render threads [default: number of CPUs]
+    #[arg(short = 'j', long, value_parser = positive::<usize>)]
+    threads: Option<usize>,
+
+    /// Samples per pixel
+    #[arg(short, long, value_parser = positive::<usize>)]
+    samples: Option<usize>,
+
+    /// Reconstruction filter: box, tent, gaussian or mitchell
+    #[arg(long)]
+    filter: Option<Filter>,
+
+    /// Seed for sample placement, renders with the same seed are identical
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Maximum number of reflection and refraction bounces of the whitted integrator. The other
+    /// integrators reject it, path tracing ends paths by Russian roulette instead
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// Distance secondary rays are offset from surfaces, raise it for large scenes
+    #[arg(long, value_parser = positive::<f64>)]
+    ray_offset: Option<f64>,
+
+    /// Tone mapping operator for 8-bit outputs: clamp, exposure, reinhard or aces
+    #[arg(long, default_value_t = ToneMapOperator::Clamp)]
+    tone_map: ToneMapOperator,
+
+    /// Exposure adjustment in stops applied before tone mapping
+    #[arg(long, default_value_t = 0., allow_negative_numbers = true)]
+    exposure: f64,
+
+    /// Encode 8-bit outputs with a plain gamma curve instead of sRGB
+    #[arg(long, value_parser = positive::<f64>)]
+    gamma: Option<f64>,
+
+    /// Also save auxiliary buffers next to the output, e.g. output.depth.exr: depth, normal,
+    /// position, object_id, material_id, direct or indirect. Data buffers saved in 8-bit formats
+    /// are fitted into their range, use .exr or .hdr to keep their values
+    #[arg(long, value_delimiter = ',')]
+    aov: Vec<Aov>,
+}
+
+fn positive<T>(arg: &str) -> Result<T, String>
+where
+    T: FromStr + PartialOrd + Default,
+    T::Err: Display,
+{
+    match arg.parse::<T>() {
+        // NaN isn't ordered against zero, so it is rejected too
+        Ok(value) if value.partial_cmp(&T::default()) == Some(Ordering::Greater) => Ok(value),
+        Ok(_) => Err("must be greater than zero".to_owned()),
+        Err(err) => Err(format!("{err}")),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.max_depth.is_some() && args.integrator != IntegratorKind::Whitted {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "--max-depth only applies to the whitted integrator, not {}",
+                    args.integrator
+                ),
+            )
+            .exit();
+    }
+
+    let mut scene = match Scene::from_file(&args.scene) {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("error: {}: {err}", args.scene.display());
+            return ExitCode::FAILURE;
+        }
     };
-    let mirror = Material {
-        diffuse_color: Vec3::new(1.0, 1.0, 1.0),
-        diffuse_albedo: 0.0,
-        specular_exponent: 1425.,
-        specular_albedo: 10.0,
-        reflective_albedo: 0.8,
-        refractive_index: 1.0,
-        refractive_albedo: 0.0,
+    scene.cam.width = args.width.unwrap_or(scene.cam.width);
+    scene.cam.height = args.height.unwrap_or(scene.cam.height);
+
+    let defaults = RenderSettings::default();
+    let settings = RenderSettings {
+        threads: args.threads.unwrap_or(defaults.threads),
+        samples: args.samples.unwrap_or(defaults.samples),
+        filter: args.filter.unwrap_or(defaults.filter),
+        seed: args.seed.unwrap_or(defaults.seed),
+        max_depth: args.max_depth.unwrap_or(defaults.max_depth),
+        ray_offset: args.ray_offset.unwrap_or(defaults.ray_offset),
+        ..defaults
     };
-    let glass = Material {
-        diffuse_color: Vec3::new(0.6, 0.7, 0.8),
-        diffuse_albedo: 0.0,
-        specular_exponent: 125.,
-        specular_albedo: 0.5,
-        reflective_albedo: 0.1,
-        refractive_index: 1.5,
-        refractive_albedo: 0.8,
+
+    let tone_mapping = ToneMapping {
+        exposure: args.exposure,
+        operator: args.tone_map,
+        encoding: args.gamma.map_or(Encoding::Srgb, Encoding::Gamma),
     };
 
-    let scene = Scene {
-        cam: Cam {
-            width: 1024,
-            height: 768,
-            fov: f64::to_radians(60.),
-            pos: Vec3::new(0., 0., 0.),
-            dir: Vec3::new(0., 0., -1.),
-        },
-        background_color: Vec3::new(0.2, 0.7, 0.8),
-        objects: vec![
-            Box::new(objects::sphere::Sphere::new(
-                Vec3::new(-3., 0., -16.),
-                2.,
-                ivory,
-            )),
-            Box::new(objects::sphere::Sphere::new(
-                Vec3::new(-1., -1.5, -12.),
-                2.,
-                glass,
-            )),
-            Box::new(objects::sphere::Sphere::new(
-                Vec3::new(1.5, -0.5, -18.),
-                3.,
-                red_rubber,
-            )),
-            Box::new(objects::sphere::Sphere::new(
-                Vec3::new(7., 5., -18.),
-                4.,
-                mirror,
-            )),
-            Box::new(objects::circular_plane::CircularPlane::new(
-                Vec3::new(1., -4., -15.),
-                7.,
-                red_rubber,
-                Vec3::new(1., 0., 0.),
-                Vec3::new(0., 0., 1.),
-            )),
-        ],
-        lights: vec![
-            Box::new(light::Point::new(
-                Vec3::new(-20., 20., 20.),
-                1.5,
-                Vec3::new(1., 1., 1.),
-            )),
-            Box::new(light::Point::new(
-                Vec3::new(30., 50., -25.),
-                1.8,
-                Vec3::new(1., 1., 1.),
-            )),
-            Box::new(light::Point::new(
-                Vec3::new(30., 20., 30.),
-                1.7,
-                Vec3::new(1., 1., 1.),
-            )),
-        ],
+    let (img, aovs) = render_with_aovs(
+        &scene,
+        args.integrator.integrator().as_ref(),
+        &settings,
+        &args.aov,
+    );
+    if let Err(err) = output::save(&img, &args.output, &tone_mapping) {
+        eprintln!("error: {}: {err}", args.output.display());
+        return ExitCode::FAILURE;
+    }
+
+    // Data buffers are saved without exposure or an sRGB curve, fitted into [0, 1] unless the
+    // format keeps their values
+    let raw = ToneMapping {
+        encoding: Encoding::Gamma(1.),
+        ..ToneMapping::default()
     };
+    for (aov, img) in args.aov.iter().zip(&aovs) {
+        let path = aov.path(&args.output);
+        let result = if aov.is_color() {
+            output::save(img, &path, &tone_mapping)
+        } else if output::is_hdr(&path) {
+            output::save(img, &path, &raw)
+        } else {
+            output::save(&aov.to_unit_range(img), &path, &raw)
+        };
+        if let Err(err) = result {
+            eprintln!("error: {}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::Args;
+
+    #[test]
+    fn test_args() {
+        let args = Args::try_parse_from([
+            "raytracer",
+            "scene.toml",
+            "--width",
+            "320",
+            "--samples",
+            "4",
+            "--ray-offset",
+            "0.01",
+        ])
+        .unwrap();
+        assert_eq!((Some(320), None), (args.width, args.height));
+        assert_eq!((Some(4), Some(0.01)), (args.samples, args.ray_offset));
+        assert_eq!("output.png", args.output.to_str().unwrap());
 
-    let img = render(&scene);
-    img.save("output.png").unwrap();
+        for invalid in [
+            ["--width", "0"],
+            ["--threads", "-2"],
+            ["--samples", "many"],
+            ["--ray-offset", "0"],
+            ["--ray-offset", "NaN"],
+            ["--gamma", "-1"],
+        ] {
+            let args = Args::try_parse_from(["raytracer", "scene.toml", invalid[0], invalid[1]]);
+            assert!(args.is_err(), "{invalid:?}");
+        }
+        assert!(Args::try_parse_from(["raytracer"]).is_err());
+    }
 }
@@ -1,7 +1,104 @@
+use serde::Deserialize;
+
+use crate::texture::TextureId;
 use crate::Vec3;
 
+// How a dielectric splits light between reflection and refraction
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fresnel {
+    Schlick,
+    Exact,
+}
+
+impl Fresnel {
+    // Fraction of light reflected when going from a medium with refractive index n1 into n2
+    pub fn reflectance(&self, cos_incident: f64, n1: f64, n2: f64) -> f64 {
+        let sin_transmitted_squared = (n1 / n2).powi(2) * (1. - cos_incident * cos_incident);
+        if sin_transmitted_squared >= 1. {
+            return 1.; // Total internal reflection
+        }
+        let cos_transmitted = (1. - sin_transmitted_squared).sqrt();
+
+        match self {
+            Fresnel::Schlick => {
+                let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+                // The approximation only holds for the angle on the optically thinner side
+                let cos = if n1 > n2 {
+                    cos_transmitted
+                } else {
+                    cos_incident
+                };
+                r0 + (1. - r0) * (1. - cos).powi(5)
+            }
+            Fresnel::Exact => {
+                let s_polarized = (n1 * cos_incident - n2 * cos_transmitted)
+                    / (n1 * cos_incident + n2 * cos_transmitted);
+                let p_polarized = (n1 * cos_transmitted - n2 * cos_incident)
+                    / (n1 * cos_transmitted + n2 * cos_incident);
+                (s_polarized * s_polarized + p_polarized * p_polarized) / 2.
+            }
+        }
+    }
+}
+
+// Surface properties of an object, under one of two reflection models
 #[derive(Copy, Clone, Debug, PartialEq)]
-pub struct Material {
+pub enum Material {
+    Phong(Phong),
+    Pbr(Pbr),
+}
+
+impl Material {
+    pub fn refractive_index(&self) -> f64 {
+        match self {
+            Material::Phong(phong) => phong.refractive_index,
+            Material::Pbr(pbr) => pbr.ior,
+        }
+    }
+
+    // Tint of the light that passes straight through, black for opaque materials
+    pub fn transmittance(&self) -> Vec3<f64> {
+        match self {
+            Material::Phong(phong) if phong.refractive_albedo > 0. => {
+                phong.diffuse_color * phong.refractive_albedo
+            }
+            Material::Phong(_) => Vec3::new(0., 0., 0.),
+            Material::Pbr(pbr) => pbr.base_color * ((1. - pbr.metallic) * pbr.transmission),
+        }
+    }
+
+    // Radiance given off by the surface itself, from both of its sides
+    pub fn emission(&self) -> Vec3<f64> {
+        match self {
+            Material::Phong(phong) => phong.emission * phong.emission_strength,
+            Material::Pbr(pbr) => pbr.emission * pbr.emission_strength,
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission() != Vec3::new(0., 0., 0.)
+    }
+
+    pub fn normal_map(&self) -> Option<TextureId> {
+        match self {
+            Material::Phong(phong) => phong.textures.normal,
+            Material::Pbr(pbr) => pbr.textures.normal,
+        }
+    }
+
+    pub fn height_map(&self) -> Option<(TextureId, f64)> {
+        match self {
+            Material::Phong(phong) => phong.textures.height,
+            Material::Pbr(pbr) => pbr.textures.height,
+        }
+    }
+}
+
+// The original ad hoc model: Lambertian and Phong terms plus perfect reflection and refraction,
+// each scaled by its own albedo
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Phong {
     pub diffuse_color: Vec3<f64>,
     pub diffuse_albedo: f64,
     pub specular_exponent: f64,
@@ -9,4 +106,81 @@ pub struct Material {
     pub reflective_albedo: f64,
     pub refractive_index: f64,
     pub refractive_albedo: f64,
+    // When set, refractive_albedo is split between reflection and refraction by the Fresnel
+    // equations instead of being refracted as a whole
+    pub fresnel: Option<Fresnel>,
+    // Spreads reflections and refractions around their ideal direction, 0 keeps them perfect and
+    // 1 is the roughest
+    pub roughness: f64,
+    pub emission: Vec3<f64>,
+    pub emission_strength: f64,
+    pub textures: MaterialTextures,
+}
+
+impl From<Phong> for Material {
+    fn from(phong: Phong) -> Self {
+        Material::Phong(phong)
+    }
+}
+
+// Textures multiply the parameters they are named after
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MaterialTextures {
+    pub diffuse_color: Option<TextureId>,
+    pub diffuse_albedo: Option<TextureId>,
+    pub specular_albedo: Option<TextureId>,
+    pub reflective_albedo: Option<TextureId>,
+    pub roughness: Option<TextureId>,
+    pub emission: Option<TextureId>,
+    // Tangent space normals encoded as colors, with +z pointing away from the surface. It takes
+    // precedence over the height map
+    pub normal: Option<TextureId>,
+    // Bump map and how far a value of 1 raises the surface
+    pub height: Option<(TextureId, f64)>,
+}
+
+// The metallic/roughness model of glTF: a GGX microfacet specular lobe over a Lambertian base,
+// with metals tinting their reflections by the base color instead
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pbr {
+    pub base_color: Vec3<f64>,
+    pub metallic: f64,
+    pub roughness: f64, // Perceptual, the GGX alpha is its square
+    pub ior: f64,
+    pub emission: Vec3<f64>,
+    pub emission_strength: f64,
+    pub transmission: f64, // Share of the non-metallic base that refracts instead of diffusing
+    pub textures: PbrTextures,
+}
+
+impl Default for Pbr {
+    // The glTF defaults, a fully rough white metal
+    fn default() -> Self {
+        Self {
+            base_color: Vec3::new(1., 1., 1.),
+            metallic: 1.,
+            roughness: 1.,
+            ior: 1.5,
+            emission: Vec3::new(0., 0., 0.),
+            emission_strength: 1.,
+            transmission: 0.,
+            textures: PbrTextures::default(),
+        }
+    }
+}
+
+impl From<Pbr> for Material {
+    fn from(pbr: Pbr) -> Self {
+        Material::Pbr(pbr)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PbrTextures {
+    pub base_color: Option<TextureId>,
+    pub metallic: Option<TextureId>,
+    pub roughness: Option<TextureId>,
+    pub emission: Option<TextureId>,
+    pub normal: Option<TextureId>,
+    pub height: Option<(TextureId, f64)>,
 }
@@ -0,0 +1,90 @@
+use crate::objects::RayIntersect;
+
+const VACUUM_REFRACTIVE_INDEX: f64 = 1.;
+
+// Volumes a ray is currently inside of with their refractive indices, innermost last. Exiting a
+// volume removes its own entry, so overlapping and nested volumes refract with the actual
+// indices on both sides of every interface, even when several of them share an index
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaStack {
+    media: Vec<(usize, f64)>, // Scene::volume_id and refractive index
+}
+
+// What happens to the media when a ray is refracted through a surface
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Interface {
+    pub n1: f64, // Refractive index on the incident side
+    pub n2: f64, // Refractive index on the transmitted side
+    entering: bool,
+    closed: bool,
+    volume: usize,
+}
+
+impl MediaStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn current_refractive_index(&self) -> f64 {
+        self.media
+            .last()
+            .map_or(VACUUM_REFRACTIVE_INDEX, |&(_, refractive_index)| {
+                refractive_index
+            })
+    }
+
+    // Entering is told by the outward normal facing against the ray. Volume is the
+    // Scene::volume_id of the object
+    pub fn interface(&self, obj: &dyn RayIntersect, volume: usize, entering: bool) -> Interface {
+        let current = self.current_refractive_index();
+        let obj_index = obj.get_material().refractive_index();
+
+        let (n1, n2) = if !obj.is_closed() {
+            // Infinitely thin surfaces have the same medium on both sides
+            (current, current)
+        } else if entering {
+            (current, obj_index)
+        } else {
+            match self.position(volume) {
+                Some(i) => (current, self.without(i).current_refractive_index()),
+                None => (obj_index, current), // Started inside of the object
+            }
+        };
+
+        Interface {
+            n1,
+            n2,
+            entering,
+            closed: obj.is_closed(),
+            volume,
+        }
+    }
+
+    // Media for a ray transmitted through the interface
+    pub fn refracted(&self, interface: &Interface, obj: &dyn RayIntersect) -> Self {
+        let obj_index = obj.get_material().refractive_index();
+
+        if !interface.closed {
+            self.clone()
+        } else if interface.entering {
+            let mut media = self.clone();
+            media.media.push((interface.volume, obj_index));
+            media
+        } else {
+            match self.position(interface.volume) {
+                Some(i) => self.without(i),
+                None => self.clone(),
+            }
+        }
+    }
+
+    fn position(&self, volume: usize) -> Option<usize> {
+        self.media.iter().rposition(|&(i, _)| i == volume)
+    }
+
+    fn without(&self, position: usize) -> Self {
+        let mut media = self.clone();
+        media.media.remove(position);
+        media
+    }
+}
@@ -0,0 +1,97 @@
+use crate::sampling::Rng;
+use crate::vector::Vec3;
+
+// Ken Perlin's improved gradient noise, with the permutation shuffled from a seed instead of the
+// reference one so that different seeds give unrelated patterns
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Perlin {
+    permutation: Vec<usize>, // 0..256 shuffled, twice in a row to skip wrapping indices
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut permutation: Vec<usize> = (0..256).collect();
+        for i in (1..256).rev() {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            permutation.swap(i, j);
+        }
+        permutation.extend_from_within(..);
+
+        Self { permutation }
+    }
+
+    // Smooth noise in about [-1, 1], 0 at integer lattice points
+    pub fn noise(&self, point: Vec3<f64>) -> f64 {
+        let floor = [point[0].floor(), point[1].floor(), point[2].floor()];
+        let [x, y, z] = [
+            point[0] - floor[0],
+            point[1] - floor[1],
+            point[2] - floor[2],
+        ];
+        let [xi, yi, zi] = floor.map(|i| (i as i64).rem_euclid(256) as usize);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let p = &self.permutation;
+        let a = p[xi] + yi;
+        let (aa, ab) = (p[a] + zi, p[a + 1] + zi);
+        let b = p[xi + 1] + yi;
+        let (ba, bb) = (p[b] + zi, p[b + 1] + zi);
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1., y, z)),
+                lerp(u, grad(p[ab], x, y - 1., z), grad(p[bb], x - 1., y - 1., z)),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.),
+                    grad(p[ba + 1], x - 1., y, z - 1.),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1., z - 1.),
+                    grad(p[bb + 1], x - 1., y - 1., z - 1.),
+                ),
+            ),
+        )
+    }
+
+    // Sum of the absolute noise over octaves of doubling frequency and halving amplitude
+    pub fn turbulence(&self, point: Vec3<f64>, octaves: usize) -> f64 {
+        let mut sum = 0.;
+        let mut frequency = 1.;
+        for _ in 0..octaves {
+            sum += self.noise(point * frequency).abs() / frequency;
+            frequency *= 2.;
+        }
+        sum
+    }
+}
+
+// 6t^5 - 15t^4 + 10t^3, which has zero first and second derivatives at 0 and 1
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// Dot product with one of 12 gradients pointing to the edges of a cube, picked by the hash
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
@@ -1,5 +1,9 @@
+use std::f64::consts::PI;
+
+use crate::bvh::Aabb;
 use crate::material::Material;
-use crate::objects::RayIntersect;
+use crate::objects::{Intersection, RayIntersect, SurfaceSample};
+use crate::sampling::uniform_disk;
 use crate::vector::{Cross, Norm, Vec3};
 use crate::Normalize;
 
@@ -32,11 +36,7 @@ impl CircularPlane {
 }
 
 impl RayIntersect for CircularPlane {
-    fn ray_intersect(
-        &self,
-        ray_origin: Vec3<f64>,
-        ray_dir: Vec3<f64>,
-    ) -> Option<(f64, Vec3<f64>, Vec3<f64>)> {
+    fn ray_intersect(&self, ray_origin: Vec3<f64>, ray_dir: Vec3<f64>) -> Option<Intersection> {
         let norm = self.plane_vector_a.cross(self.plane_vector_b).normalize();
 
         // Plane is defined by ax + by + cz + d = 0
@@ -68,15 +68,56 @@ impl RayIntersect for CircularPlane {
         {
             None
         } else {
-            Some((
-                (hit_point - ray_origin).norm(),
+            // Planar mapping across the diameter, u along plane_vector_a
+            let u_axis = self.plane_vector_a.normalize();
+            let v_axis = norm.cross(u_axis);
+            let local = (hit_point - self.center) * (0.5 / self.radius);
+
+            Some(Intersection {
+                dist: (hit_point - ray_origin).norm(),
                 hit_point,
-                if norm * ray_dir > 0. { -norm } else { norm },
-            ))
+                norm_dir: if norm * ray_dir > 0. { -norm } else { norm },
+                uv: (0.5 + local * u_axis, 0.5 + local * v_axis),
+                tangent: u_axis * (2. * self.radius),
+                bitangent: v_axis * (2. * self.radius),
+            })
         }
     }
 
     fn get_material(&self) -> &Material {
         &self.material
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let norm = self.plane_vector_a.cross(self.plane_vector_b).normalize();
+
+        // A disk spans radius * sin(angle between its normal and the axis) along each axis.
+        // Padding keeps the box from being flat when the disk is axis aligned
+        let extent =
+            |axis: usize| self.radius * (1. - norm[axis] * norm[axis]).max(0.).sqrt() + 1e-6;
+        let extent = Vec3::new(extent(0), extent(1), extent(2));
+
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        let norm = self.plane_vector_a.cross(self.plane_vector_b).normalize();
+        let u_axis = self.plane_vector_a.normalize();
+        let v_axis = norm.cross(u_axis);
+        let (x, y) = uniform_disk(u, v);
+
+        SurfaceSample {
+            point: self.center + (u_axis * x + v_axis * y) * self.radius,
+            norm_dir: norm,
+            uv: (0.5 + x / 2., 0.5 + y / 2.),
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        false
+    }
 }
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::material::{Material, MaterialTextures, Phong};
+use crate::objects::triangle::Triangle;
+use crate::objects::RayIntersect;
+use crate::vector::Vec3;
+
+// A triangle soup bounding a volume. Meshes are added to a scene triangle by triangle so that the
+// scene BVH covers the individual triangles rather than the whole mesh
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mesh {
+    triangles: Vec<Triangle>,
+    groups: Vec<String>, // Of the OBJ file the mesh was loaded from
+}
+
+impl Mesh {
+    pub fn new(triangles: Vec<Triangle>) -> Self {
+        Self {
+            triangles: triangles.into_iter().map(Triangle::closed).collect(),
+            groups: Vec::new(),
+        }
+    }
+
+    // Loads a Wavefront OBJ file. Each group gets its material from the `group_materials`
+    // overrides, or from the MTL library referenced by the file, or falls back to
+    // `default_material`
+    pub fn from_obj<P: AsRef<Path>>(
+        path: P,
+        default_material: Material,
+        group_materials: &HashMap<String, Material>,
+    ) -> Result<Self, tobj::LoadError> {
+        let (models, mtl_materials) = tobj::load_obj(
+            path.as_ref(),
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ignore_points: true,
+                ignore_lines: true,
+            },
+        )?;
+        // A missing or broken MTL library leaves every group to the other two
+        let mtl_materials: Vec<Material> = mtl_materials
+            .map(|materials| materials.iter().map(from_mtl).collect())
+            .unwrap_or_default();
+
+        let mut triangles = Vec::new();
+        for model in &models {
+            let mesh = &model.mesh;
+            let material = group_materials
+                .get(&model.name)
+                .or_else(|| mesh.material_id.and_then(|i| mtl_materials.get(i)))
+                .copied()
+                .unwrap_or(default_material);
+
+            let position = |i: u32| {
+                let i = i as usize * 3;
+                Vec3::new(
+                    mesh.positions[i],
+                    mesh.positions[i + 1],
+                    mesh.positions[i + 2],
+                )
+            };
+            let normal = |i: u32| {
+                let i = i as usize * 3;
+                Vec3::new(mesh.normals[i], mesh.normals[i + 1], mesh.normals[i + 2])
+            };
+
+            let uv = |i: u32| {
+                let i = i as usize * 2;
+                (mesh.texcoords[i], mesh.texcoords[i + 1])
+            };
+
+            for face in mesh.indices.chunks_exact(3) {
+                let vertices = [position(face[0]), position(face[1]), position(face[2])];
+                let mut triangle = if mesh.normals.is_empty() {
+                    Triangle::new(vertices, material)
+                } else {
+                    Triangle::with_normals(
+                        vertices,
+                        [normal(face[0]), normal(face[1]), normal(face[2])],
+                        material,
+                    )
+                };
+                if !mesh.texcoords.is_empty() {
+                    triangle = triangle.with_uvs([uv(face[0]), uv(face[1]), uv(face[2])]);
+                }
+                triangles.push(triangle);
+            }
+        }
+
+        Ok(Self {
+            groups: models.into_iter().map(|model| model.name).collect(),
+            ..Self::new(triangles)
+        })
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    pub fn into_objects(self) -> impl Iterator<Item = Box<dyn RayIntersect>> {
+        self.triangles
+            .into_iter()
+            .map(|i| Box::new(i) as Box<dyn RayIntersect>)
+    }
+}
+
+fn from_mtl(mtl: &tobj::Material) -> Material {
+    let rgb = |color: Option<[f64; 3]>| color.map(|[r, g, b]| Vec3::new(r, g, b));
+    let specular = mtl.specular.unwrap_or([0., 0., 0.]);
+    let transparency = 1. - mtl.dissolve.unwrap_or(1.);
+    // tobj doesn't know the emissive color, so it ends up among the unknown parameters
+    let emission = mtl.unknown_param.get("Ke").and_then(|ke| {
+        let channels: Vec<f64> = ke
+            .split_whitespace()
+            .map_while(|i| i.parse().ok())
+            .collect();
+        match channels[..] {
+            [r, g, b] => Some(Vec3::new(r, g, b)),
+            _ => None,
+        }
+    });
+
+    Material::Phong(Phong {
+        diffuse_color: rgb(mtl.diffuse).unwrap_or(Vec3::new(0.8, 0.8, 0.8)),
+        diffuse_albedo: 1. - transparency,
+        specular_exponent: mtl.shininess.unwrap_or(10.),
+        specular_albedo: specular.into_iter().fold(0., f64::max),
+        reflective_albedo: 0.,
+        refractive_index: mtl.optical_density.unwrap_or(1.),
+        refractive_albedo: transparency,
+        fresnel: None,
+        roughness: 0.,
+        emission: emission.unwrap_or(Vec3::new(0., 0., 0.)),
+        emission_strength: 1.,
+        textures: MaterialTextures::default(),
+    })
+}
@@ -1,43 +1,88 @@
+use crate::bvh::Aabb;
 use crate::material::Material;
+use crate::sampling::{cosine_power_hemisphere, orthonormal_basis};
 use crate::vector::Vec3;
 
 pub mod circular_plane;
+pub mod mesh;
 pub mod sphere;
+pub mod triangle;
 
-pub trait RayIntersect {
-    fn ray_intersect(
-        &self,
-        ray_origin: Vec3<f64>,
-        ray_dir: Vec3<f64>,
-    ) -> Option<(f64, Vec3<f64>, Vec3<f64>)>;
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Intersection {
+    pub dist: f64, // Along the ray
+    pub hit_point: Vec3<f64>,
+    pub norm_dir: Vec3<f64>,
+    pub uv: (f64, f64), // Surface coordinates for texturing, within [0, 1] unless given otherwise
+    // How the hit point moves with u and v, zero where the mapping degenerates like at the poles
+    // of a sphere
+    pub tangent: Vec3<f64>,
+    pub bitangent: Vec3<f64>,
+}
+
+// A point picked on a surface
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SurfaceSample {
+    pub point: Vec3<f64>,
+    pub norm_dir: Vec3<f64>,
+    pub uv: (f64, f64),
+}
+
+pub trait RayIntersect: Send + Sync {
+    fn ray_intersect(&self, ray_origin: Vec3<f64>, ray_dir: Vec3<f64>) -> Option<Intersection>;
     fn get_material(&self) -> &Material;
+    fn bounding_box(&self) -> Aabb;
+
+    fn area(&self) -> f64;
+
+    // Maps a point of the unit square to a point of the surface, uniformly distributed over its
+    // area, so that emissive objects can be sampled as lights
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample;
+
+    // Whether the surface bounds a volume that rays can be inside of
+    fn is_closed(&self) -> bool {
+        true
+    }
+}
+
+// Spreads an ideal reflected or refracted direction into a lobe around it, from perfectly sharp
+// at roughness 0 to a cosine distribution at 1. u and v pick the direction
+pub fn jitter(dir: Vec3<f64>, roughness: f64, u: f64, v: f64) -> Vec3<f64> {
+    if roughness <= 0. {
+        return dir;
+    }
+
+    // A Phong lobe whose exponent falls from infinity towards 1 as roughness grows
+    let roughness = roughness.min(1.);
+    let exponent = 2. / (roughness * roughness) - 1.;
+    let local = cosine_power_hemisphere(u, v, exponent);
+    let (tangent, bitangent) = orthonormal_basis(dir);
+    tangent * local[0] + bitangent * local[1] + dir * local[2]
 }
 
 pub fn reflect(ray: Vec3<f64>, norm: Vec3<f64>) -> Vec3<f64> {
     ray - norm * 2. * (ray * norm) // ray and norm must be normalized
 }
 
-pub fn refract(ray: Vec3<f64>, norm: Vec3<f64>, refraction_index_obj: f64) -> Vec3<f64> {
-    let refraction_index_air = 1.0;
-    let mut cos_alpha = -(ray * norm).max(-1.).min(1.); // Dot product of unit vectors to get angle between them
+// Refraction from a medium with index n1 into n2. None in case of total internal reflection
+pub fn refract(ray: Vec3<f64>, norm: Vec3<f64>, n1: f64, n2: f64) -> Option<Vec3<f64>> {
+    let mut cos_alpha = -(ray * norm).clamp(-1., 1.); // Dot product of unit vectors to get angle between them
 
-    let refraction_ratio;
     let oriented_norm;
     if cos_alpha < 0. {
-        // When ray goes out from inside of an object
+        // When the normal points to the same side the ray goes to
         cos_alpha = cos_alpha.abs();
-        refraction_ratio = refraction_index_obj / refraction_index_air;
         oriented_norm = -norm;
     } else {
-        refraction_ratio = refraction_index_air / refraction_index_obj;
         oriented_norm = norm;
     }
 
+    let refraction_ratio = n1 / n2;
     let k = 1. - refraction_ratio * refraction_ratio * (1. - cos_alpha * cos_alpha); // Snell's law magic
     if k < 0. {
-        ray // Dummy vector in case of no refraction
+        None
     } else {
-        ray * refraction_ratio + oriented_norm * (refraction_ratio * cos_alpha - k.sqrt())
+        Some(ray * refraction_ratio + oriented_norm * (refraction_ratio * cos_alpha - k.sqrt()))
         // Even more magic
     }
 }
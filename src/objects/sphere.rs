@@ -1,5 +1,9 @@
+use std::f64::consts::PI;
+
+use crate::bvh::Aabb;
 use crate::material::Material;
-use crate::objects::RayIntersect;
+use crate::objects::{Intersection, RayIntersect, SurfaceSample};
+use crate::sampling::uniform_sphere;
 use crate::vector::Vec3;
 use crate::Normalize;
 
@@ -21,11 +25,7 @@ impl Sphere {
 }
 
 impl RayIntersect for Sphere {
-    fn ray_intersect(
-        &self,
-        ray_origin: Vec3<f64>,
-        ray_dir: Vec3<f64>,
-    ) -> Option<(f64, Vec3<f64>, Vec3<f64>)> {
+    fn ray_intersect(&self, ray_origin: Vec3<f64>, ray_dir: Vec3<f64>) -> Option<Intersection> {
         let cam_to_center = self.center - ray_origin;
         let directed_cam_to_center = cam_to_center * ray_dir; // Creates a right triangle with the cam_to_center vector
         let center_to_ray_distance_squared =
@@ -39,30 +39,71 @@ impl RayIntersect for Sphere {
         // Another pythagoras to find length of intersected part of the ray
         let cam_to_intersect_length_in = directed_cam_to_center - intersected_ray_part_half_length;
         let cam_to_intersect_length_out = directed_cam_to_center + intersected_ray_part_half_length;
-        let hit_point;
-        if cam_to_intersect_length_in > 0. {
+        let dist = if cam_to_intersect_length_in > 0. {
             // If origin is outside sphere
-            hit_point = ray_origin + ray_dir * cam_to_intersect_length_in;
-            Some((
-                cam_to_intersect_length_in,
-                hit_point,
-                (hit_point - self.center).normalize(),
-            ))
+            cam_to_intersect_length_in
         } else if cam_to_intersect_length_out > 0. {
             // If origin is inside sphere
-            hit_point = ray_origin + ray_dir * cam_to_intersect_length_out;
-            Some((
-                cam_to_intersect_length_out,
-                hit_point,
-                (hit_point - self.center).normalize(),
-            ))
+            cam_to_intersect_length_out
         } else {
             // If sphere is behind the ray
-            None
-        }
+            return None;
+        };
+
+        let hit_point = ray_origin + ray_dir * dist;
+        let norm_dir = (hit_point - self.center).normalize();
+        let (tangent, bitangent) = spherical_tangents(norm_dir);
+        Some(Intersection {
+            dist,
+            hit_point,
+            norm_dir,
+            uv: spherical_uv(norm_dir),
+            tangent: tangent * self.radius,
+            bitangent: bitangent * self.radius,
+        })
     }
 
     fn get_material(&self) -> &Material {
         &self.material
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - radius, self.center + radius)
+    }
+
+    fn area(&self) -> f64 {
+        4. * PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        let norm_dir = uniform_sphere(u, v);
+        SurfaceSample {
+            point: self.center + norm_dir * self.radius,
+            norm_dir,
+            uv: spherical_uv(norm_dir),
+        }
+    }
+}
+
+// Longitude and latitude of a point on the unit sphere. u grows eastwards when seen from outside
+// and v towards +y
+fn spherical_uv(norm_dir: Vec3<f64>) -> (f64, f64) {
+    let u = 0.5 - norm_dir[2].atan2(norm_dir[0]) / (2. * PI);
+    let v = 0.5 + norm_dir[1].clamp(-1., 1.).asin() / PI;
+    (u, v)
+}
+
+// Derivatives of a point on the unit sphere with respect to spherical_uv
+fn spherical_tangents(norm_dir: Vec3<f64>) -> (Vec3<f64>, Vec3<f64>) {
+    let [x, y, z] = [norm_dir[0], norm_dir[1], norm_dir[2]];
+    let horizontal = (x * x + z * z).sqrt(); // Radius of the circle of latitude
+    if horizontal < 1e-12 {
+        return (Vec3::new(0., 0., 0.), Vec3::new(0., 0., 0.));
+    }
+
+    (
+        Vec3::new(z, 0., -x) * (2. * PI),
+        Vec3::new(-y * x / horizontal, horizontal, -y * z / horizontal) * PI,
+    )
 }
@@ -0,0 +1,168 @@
+use crate::bvh::Aabb;
+use crate::material::Material;
+use crate::objects::{Intersection, RayIntersect, SurfaceSample};
+use crate::vector::{Cross, Norm, Vec3};
+use crate::Normalize;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Triangle {
+    vertices: [Vec3<f64>; 3],
+    normals: Option<[Vec3<f64>; 3]>, // Per-vertex normals for smooth shading
+    uvs: Option<[(f64, f64); 3]>,    // Per-vertex texture coordinates
+    material: Material,
+    closed: bool, // Part of a mesh bounding a volume, otherwise infinitely thin
+}
+
+impl Triangle {
+    pub fn new(vertices: [Vec3<f64>; 3], material: Material) -> Self {
+        Self {
+            vertices,
+            normals: None,
+            uvs: None,
+            material,
+            closed: false,
+        }
+    }
+
+    pub fn with_normals(
+        vertices: [Vec3<f64>; 3],
+        normals: [Vec3<f64>; 3],
+        material: Material,
+    ) -> Self {
+        Self {
+            vertices,
+            normals: Some(normals.map(|i| i.normalize())),
+            uvs: None,
+            material,
+            closed: false,
+        }
+    }
+
+    // Without texture coordinates, UVs are the barycentric coordinates of v1 and v2
+    pub fn with_uvs(self, uvs: [(f64, f64); 3]) -> Self {
+        Self {
+            uvs: Some(uvs),
+            ..self
+        }
+    }
+
+    pub fn closed(self) -> Self {
+        Self {
+            closed: true,
+            ..self
+        }
+    }
+
+    // Texture coordinates at barycentric coordinates u and v
+    fn uv_at(&self, u: f64, v: f64) -> (f64, f64) {
+        match self.uvs {
+            Some([uv0, uv1, uv2]) => (
+                uv0.0 * (1. - u - v) + uv1.0 * u + uv2.0 * v,
+                uv0.1 * (1. - u - v) + uv1.1 * u + uv2.1 * v,
+            ),
+            None => (u, v),
+        }
+    }
+}
+
+impl RayIntersect for Triangle {
+    fn ray_intersect(&self, ray_origin: Vec3<f64>, ray_dir: Vec3<f64>) -> Option<Intersection> {
+        // Möller–Trumbore: solve origin + t * dir = v0 + u * edge_a + v * edge_b
+        let [v0, v1, v2] = self.vertices;
+        let edge_a = v1 - v0;
+        let edge_b = v2 - v0;
+
+        let p = ray_dir.cross(edge_b);
+        let det = edge_a * p;
+        if det.abs() < 1e-12 {
+            // Ray is parallel to the triangle
+            return None;
+        }
+        let inv_det = 1. / det;
+
+        let origin_to_v0 = ray_origin - v0;
+        let u = (origin_to_v0 * p) * inv_det;
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+
+        let q = origin_to_v0.cross(edge_a);
+        let v = (ray_dir * q) * inv_det;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+
+        let t = (edge_b * q) * inv_det;
+        if t <= 0. {
+            // Triangle is behind the ray
+            return None;
+        }
+
+        let mut norm = match self.normals {
+            // Barycentric interpolation of vertex normals
+            Some([n0, n1, n2]) => (n0 * (1. - u - v) + n1 * u + n2 * v).normalize(),
+            None => edge_a.cross(edge_b).normalize(),
+        };
+        // Normals of open surfaces face the ray, like those of circular planes
+        if !self.closed && edge_a.cross(edge_b) * ray_dir > 0. {
+            norm = -norm;
+        }
+
+        // Barycentric coordinates are UVs whose derivatives are the edges themselves
+        let mut tangent = edge_a;
+        let mut bitangent = edge_b;
+        if let Some([uv0, uv1, uv2]) = self.uvs {
+            // Invert the 2x2 matrix mapping the derivatives to the UV deltas along the edges
+            let (du_a, dv_a) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+            let (du_b, dv_b) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+            let uv_det = du_a * dv_b - dv_a * du_b;
+            if uv_det.abs() > 1e-12 {
+                tangent = (edge_a * dv_b - edge_b * dv_a) * (1. / uv_det);
+                bitangent = (edge_b * du_a - edge_a * du_b) * (1. / uv_det);
+            }
+        }
+
+        Some(Intersection {
+            dist: t,
+            hit_point: ray_origin + ray_dir * t,
+            norm_dir: norm,
+            uv: self.uv_at(u, v),
+            tangent,
+            bitangent,
+        })
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [v0, v1, v2] = self.vertices;
+        let padding = Vec3::new(1e-6, 1e-6, 1e-6); // Axis aligned triangles have flat boxes
+
+        Aabb::new(v0 - padding, v0 + padding)
+            .union(&Aabb::new(v1, v1))
+            .union(&Aabb::new(v2, v2))
+    }
+
+    fn area(&self) -> f64 {
+        let [v0, v1, v2] = self.vertices;
+        (v1 - v0).cross(v2 - v0).norm() / 2.
+    }
+
+    // Square root warping of the unit square onto barycentric coordinates
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        let [v0, v1, v2] = self.vertices;
+        let (b1, b2) = (u.sqrt() * (1. - v), u.sqrt() * v);
+
+        SurfaceSample {
+            point: v0 + (v1 - v0) * b1 + (v2 - v0) * b2,
+            norm_dir: (v1 - v0).cross(v2 - v0).normalize(),
+            uv: self.uv_at(b1, b2),
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
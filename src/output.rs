@@ -0,0 +1,141 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::str::FromStr;
+
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageBuffer, ImageResult, Rgb, Rgb32FImage, RgbImage};
+
+use crate::vector::Vec3;
+
+// Compresses linear HDR radiance into [0, 1]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    Clamp,
+    Exposure, // 1 - e^(-x)
+    Reinhard, // x / (1 + x)
+    Aces,     // Narkowicz's fit of the ACES filmic curve
+}
+
+impl ToneMapOperator {
+    fn apply(&self, x: f64) -> f64 {
+        let x = x.max(0.);
+        match self {
+            ToneMapOperator::Clamp => x.min(1.),
+            ToneMapOperator::Exposure => 1. - (-x).exp(),
+            ToneMapOperator::Reinhard => x / (1. + x),
+            ToneMapOperator::Aces => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.)
+            }
+        }
+    }
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "clamp" => Ok(ToneMapOperator::Clamp),
+            "exposure" => Ok(ToneMapOperator::Exposure),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "aces" => Ok(ToneMapOperator::Aces),
+            _ => Err(format!(
+                "unknown tone mapping operator `{name}`, expected clamp, exposure, reinhard or aces"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ToneMapOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ToneMapOperator::Clamp => "clamp",
+            ToneMapOperator::Exposure => "exposure",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::Aces => "aces",
+        })
+    }
+}
+
+// Transfer function used to store tone mapped values in 8 bits
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Encoding {
+    Srgb,
+    Gamma(f64),
+}
+
+impl Encoding {
+    fn apply(&self, x: f64) -> f64 {
+        match *self {
+            Encoding::Srgb => {
+                if x <= 0.0031308 {
+                    x * 12.92
+                } else {
+                    1.055 * x.powf(1. / 2.4) - 0.055
+                }
+            }
+            Encoding::Gamma(gamma) => x.powf(1. / gamma),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub exposure: f64, // In stops, applied before the operator
+    pub operator: ToneMapOperator,
+    pub encoding: Encoding,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            exposure: 0.,
+            operator: ToneMapOperator::Clamp,
+            encoding: Encoding::Srgb,
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn apply(&self, hdr: &Rgb32FImage) -> RgbImage {
+        let scale = 2f64.powf(self.exposure);
+        let map = |x: f32| self.encoding.apply(self.operator.apply(x as f64 * scale));
+
+        ImageBuffer::from_fn(hdr.width(), hdr.height(), |x, y| {
+            let Rgb([r, g, b]) = *hdr.get_pixel(x, y);
+            Vec3::new(map(r), map(g), map(b)).into()
+        })
+    }
+}
+
+// Whether save keeps the values of an image at the path as they are
+pub fn is_hdr<P: AsRef<Path>>(path: P) -> bool {
+    matches!(extension(path.as_ref()).as_deref(), Some("exr" | "hdr"))
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|i| i.to_str())
+        .map(|i| i.to_ascii_lowercase())
+}
+
+// Saves linear radiance as is into OpenEXR (.exr) or Radiance HDR (.hdr) files, and tone maps
+// it into 8 bits for everything else
+pub fn save<P: AsRef<Path>>(
+    hdr: &Rgb32FImage,
+    path: P,
+    tone_mapping: &ToneMapping,
+) -> ImageResult<()> {
+    let path = path.as_ref();
+    match extension(path).as_deref() {
+        Some("exr") => hdr.save(path),
+        Some("hdr") => HdrEncoder::new(BufWriter::new(File::create(path)?)).encode(
+            hdr.pixels().copied().collect::<Vec<_>>().as_slice(),
+            hdr.width() as usize,
+            hdr.height() as usize,
+        ),
+        _ => tone_mapping.apply(hdr).save(path),
+    }
+}
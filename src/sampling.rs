@@ -0,0 +1,110 @@
+use std::f64::consts::PI;
+
+use crate::vector::{Cross, Normalize, Vec3};
+
+// Small deterministic PRNG (SplitMix64). Renders must be reproducible from a seed no matter how
+// pixels are spread across threads, so every pixel seeds its own generator
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn for_pixel(seed: u64, x: usize, y: usize) -> Self {
+        let mut rng = Self::new(seed);
+        let seed = rng.next_u64() ^ (x as u64);
+        let mut rng = Self::new(seed);
+        let seed = rng.next_u64() ^ (y as u64);
+        Self::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1. / (1u64 << 53) as f64)
+    }
+}
+
+// Exactly the requested number of jittered points in [0, 1)^2, one per cell of the largest
+// square grid that fits. The rest are stratified along x only, one per column of their own
+pub fn stratified(samples: usize, rng: &mut Rng) -> Vec<(f64, f64)> {
+    let grid_size = (samples as f64).sqrt() as usize;
+    let cell_size = 1. / grid_size as f64;
+    let remainder = samples - grid_size * grid_size;
+
+    let mut points: Vec<(f64, f64)> = (0..grid_size * grid_size)
+        .map(|cell| {
+            (
+                ((cell % grid_size) as f64 + rng.next_f64()) * cell_size,
+                ((cell / grid_size) as f64 + rng.next_f64()) * cell_size,
+            )
+        })
+        .collect();
+    points.extend((0..remainder).map(|column| {
+        (
+            (column as f64 + rng.next_f64()) / remainder as f64,
+            rng.next_f64(),
+        )
+    }));
+    points
+}
+
+// Two unit vectors that together with the normalized n form an orthonormal basis
+pub fn orthonormal_basis(n: Vec3<f64>) -> (Vec3<f64>, Vec3<f64>) {
+    // Any vector that isn't parallel to n will do as a starting point
+    let helper = if n[0].abs() > 0.9 {
+        Vec3::new(0., 1., 0.)
+    } else {
+        Vec3::new(1., 0., 0.)
+    };
+    let tangent = n.cross(helper).normalize();
+    let bitangent = n.cross(tangent);
+
+    (tangent, bitangent)
+}
+
+// Maps a point of the unit square to a uniformly distributed point of the unit disk
+pub fn uniform_disk(u: f64, v: f64) -> (f64, f64) {
+    let radius = u.sqrt();
+    let angle = 2. * PI * v;
+    (radius * angle.cos(), radius * angle.sin())
+}
+
+// Maps a point of the unit square to a uniformly distributed point of the unit sphere
+pub fn uniform_sphere(u: f64, v: f64) -> Vec3<f64> {
+    let z = 1. - 2. * u;
+    let radius = (1. - z * z).max(0.).sqrt();
+    let angle = 2. * PI * v;
+    Vec3::new(radius * angle.cos(), radius * angle.sin(), z)
+}
+
+// Maps a point of the unit square to a direction around the z axis of the upper hemisphere,
+// with a density proportional to the cosine of its angle to the axis raised to exponent
+pub fn cosine_power_hemisphere(u: f64, v: f64, exponent: f64) -> Vec3<f64> {
+    let cos_angle = u.powf(1. / (exponent + 1.));
+    let sin_angle = (1. - cos_angle * cos_angle).max(0.).sqrt();
+    let rotation = 2. * PI * v;
+    Vec3::new(
+        sin_angle * rotation.cos(),
+        sin_angle * rotation.sin(),
+        cos_angle,
+    )
+}
+
+// Maps a point of the unit square to a direction around the z axis of the upper hemisphere,
+// with a density proportional to the cosine of its angle to the axis
+pub fn cosine_hemisphere(u: f64, v: f64) -> Vec3<f64> {
+    let (x, y) = uniform_disk(u, v);
+    Vec3::new(x, y, (1. - x * x - y * y).max(0.).sqrt())
+}
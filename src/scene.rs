@@ -1,12 +1,156 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::bvh::Bvh;
 use crate::light::LightIntensity;
+use crate::loader::{load_scene, SceneError};
+use crate::material::Material;
 use crate::objects::RayIntersect;
-use crate::vector::Vec3;
+use crate::texture::{Texture, TextureId};
+use crate::vector::{Cross, Hadamard, Norm, Normalize, Vec3};
 
 pub struct Scene {
     pub cam: Cam,
     pub background_color: Vec3<f64>,
-    pub objects: Vec<Box<dyn RayIntersect>>,
+    objects: Vec<Box<dyn RayIntersect>>,
+    material_ids: Vec<usize>, // Objects with equal materials share an ID
+    emitters: Vec<usize>,     // Objects with emissive materials, which light the scene too
+    volume_ids: Vec<usize>,   // Objects bounding the same volume share an ID
     pub lights: Vec<Box<dyn LightIntensity>>,
+    textures: Vec<Box<dyn Texture>>, // Referenced by materials through TextureId
+    bvh: Bvh, // Built once from objects, which is why they can't be modified afterwards
+}
+
+impl Scene {
+    pub fn new(
+        cam: Cam,
+        background_color: Vec3<f64>,
+        objects: Vec<Box<dyn RayIntersect>>,
+        lights: Vec<Box<dyn LightIntensity>>,
+    ) -> Self {
+        let bvh = Bvh::build(&objects);
+
+        let mut materials: Vec<Material> = Vec::new();
+        let material_ids = objects
+            .iter()
+            .map(|i| {
+                let material = i.get_material();
+                materials
+                    .iter()
+                    .position(|known| known == material)
+                    .unwrap_or_else(|| {
+                        materials.push(*material);
+                        materials.len() - 1
+                    })
+            })
+            .collect();
+        let emitters = (0..objects.len())
+            .filter(|&i| objects[i].get_material().is_emissive())
+            .collect();
+
+        let volume_ids = (0..objects.len()).collect();
+
+        Self {
+            cam,
+            background_color,
+            objects,
+            material_ids,
+            emitters,
+            volume_ids,
+            lights,
+            textures: Vec::new(),
+            bvh,
+        }
+    }
+
+    pub fn with_textures(self, textures: Vec<Box<dyn Texture>>) -> Self {
+        Self { textures, ..self }
+    }
+
+    // Ranges of objects that together bound a single volume, like the triangles of a mesh
+    pub fn with_meshes(mut self, meshes: &[Range<usize>]) -> Self {
+        for mesh in meshes {
+            for i in mesh.clone() {
+                self.volume_ids[i] = mesh.start;
+            }
+        }
+        self
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        load_scene(
+            &fs::read_to_string(path)?,
+            path.parent().unwrap_or(Path::new("")),
+        )
+    }
+
+    pub fn objects(&self) -> &[Box<dyn RayIntersect>] {
+        &self.objects
+    }
+
+    pub fn material_id(&self, object_id: usize) -> usize {
+        self.material_ids[object_id]
+    }
+
+    pub fn emitters(&self) -> &[usize] {
+        &self.emitters
+    }
+
+    // Identifies the volume an object bounds, for telling which one a ray leaves
+    pub fn volume_id(&self, object_id: usize) -> usize {
+        self.volume_ids[object_id]
+    }
+
+    pub fn texture(&self, id: TextureId) -> &dyn Texture {
+        self.textures[id.0].as_ref()
+    }
+
+    // Material of an object with its textures looked up at a point of its surface
+    pub fn material_at(&self, object_id: usize, uv: (f64, f64), point: Vec3<f64>) -> Material {
+        let value = |texture: Option<TextureId>| {
+            texture.map_or(1., |texture| self.texture(texture).value(uv, point))
+        };
+        let color = |texture: Option<TextureId>, color: Vec3<f64>| match texture {
+            Some(texture) => color.hadamard(self.texture(texture).color(uv, point)),
+            None => color,
+        };
+
+        match *self.objects[object_id].get_material() {
+            Material::Phong(mut material) => {
+                let textures = material.textures;
+                material.diffuse_color = color(textures.diffuse_color, material.diffuse_color);
+                material.diffuse_albedo *= value(textures.diffuse_albedo);
+                material.specular_albedo *= value(textures.specular_albedo);
+                material.reflective_albedo *= value(textures.reflective_albedo);
+                material.roughness *= value(textures.roughness);
+                material.emission = color(textures.emission, material.emission);
+                Material::Phong(material)
+            }
+            Material::Pbr(mut material) => {
+                let textures = material.textures;
+                material.base_color = color(textures.base_color, material.base_color);
+                material.metallic *= value(textures.metallic);
+                material.roughness *= value(textures.roughness);
+                material.emission = color(textures.emission, material.emission);
+                Material::Pbr(material)
+            }
+        }
+    }
+
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+}
+
+impl FromStr for Scene {
+    type Err = SceneError;
+
+    fn from_str(source: &str) -> Result<Self, SceneError> {
+        load_scene(source, Path::new(""))
+    }
 }
 
 pub struct Cam {
@@ -14,5 +158,62 @@ pub struct Cam {
     pub height: usize,
     pub fov: f64,
     pub pos: Vec3<f64>,
-    pub dir: Vec3<f64>,
+    basis: (Vec3<f64>, Vec3<f64>, Vec3<f64>),
+}
+
+impl Cam {
+    // up only has to lean towards the top of the image, but must not be parallel to dir
+    pub fn new(
+        pos: Vec3<f64>,
+        dir: Vec3<f64>,
+        up: Vec3<f64>,
+        fov: f64,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        assert!(
+            dir.cross(up).norm() > 0.,
+            "camera up must not be parallel to the view direction"
+        );
+
+        let forward = dir.normalize();
+        let right = forward.cross(up).normalize();
+        let up = right.cross(forward); // Already unit length since right and forward are orthonormal
+
+        Self {
+            width,
+            height,
+            fov,
+            pos,
+            basis: (forward, right, up),
+        }
+    }
+
+    pub fn look_at(
+        pos: Vec3<f64>,
+        target: Vec3<f64>,
+        up: Vec3<f64>,
+        fov: f64,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        Self::new(pos, target - pos, up, fov, width, height)
+    }
+
+    // Orthonormal camera frame as (forward, right, up)
+    pub fn basis(&self) -> (Vec3<f64>, Vec3<f64>, Vec3<f64>) {
+        self.basis
+    }
+
+    // Direction of a ray through the given point of the image plane, measured in pixels
+    pub fn ray_dir(&self, x: f64, y: f64) -> Vec3<f64> {
+        let (forward, right, up) = self.basis();
+
+        let aspect_ratio = self.width as f64 / self.height as f64;
+        let half_height = f64::tan(self.fov / 2.);
+        let screen_x = (2. * x / self.width as f64 - 1.) * half_height * aspect_ratio;
+        let screen_y = -(2. * y / self.height as f64 - 1.) * half_height;
+
+        (forward + right * screen_x + up * screen_y).normalize()
+    }
 }
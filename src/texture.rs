@@ -0,0 +1,261 @@
+use std::f64::consts::PI;
+use std::path::Path;
+
+use image::{DynamicImage, ImageResult, Rgb32FImage};
+use serde::Deserialize;
+
+use crate::noise::Perlin;
+use crate::vector::Vec3;
+
+// Varies a material parameter over a surface
+pub trait Texture: Send + Sync {
+    fn color(&self, uv: (f64, f64), point: Vec3<f64>) -> Vec3<f64>;
+
+    // For parameters that aren't colors
+    fn value(&self, uv: (f64, f64), point: Vec3<f64>) -> f64 {
+        let color = self.color(uv, point);
+        (color[0] + color[1] + color[2]) / 3.
+    }
+}
+
+// Index into the textures of a scene
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureId(pub usize);
+
+// What happens to UVs outside of [0, 1]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Wrap {
+    #[default]
+    Repeat,
+    Mirror, // Repeats flipping every other copy, so that edges always match
+    Clamp,  // Stretches the border pixels
+}
+
+impl Wrap {
+    fn apply(&self, i: i64, size: u32) -> u32 {
+        let size = size as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+            Wrap::Clamp => i.clamp(0, size - 1),
+        };
+        i as u32
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageTexture {
+    image: Rgb32FImage, // Linear colors
+    wrap: Wrap,
+    interpolation: Interpolation,
+    scale: f64, // How many times the image repeats across the UV square
+}
+
+impl ImageTexture {
+    pub fn new(image: Rgb32FImage) -> Self {
+        Self {
+            image,
+            wrap: Wrap::default(),
+            interpolation: Interpolation::default(),
+            scale: 1.,
+        }
+    }
+
+    // Floating point images are taken as linear, all others as sRGB encoded
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        Self::load(path, true)
+    }
+
+    // For images holding data rather than colors
+    pub fn open_linear<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        Self::load(path, false)
+    }
+
+    fn load<P: AsRef<Path>>(path: P, srgb: bool) -> ImageResult<Self> {
+        let image = image::open(path)?;
+        let linear = !srgb
+            || matches!(
+                image,
+                DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+            );
+
+        let mut image = image.into_rgb32f();
+        if !linear {
+            for channel in image.iter_mut() {
+                *channel = srgb_to_linear(*channel as f64) as f32;
+            }
+        }
+        Ok(Self::new(image))
+    }
+
+    pub fn with_wrap(self, wrap: Wrap) -> Self {
+        Self { wrap, ..self }
+    }
+
+    pub fn with_interpolation(self, interpolation: Interpolation) -> Self {
+        Self {
+            interpolation,
+            ..self
+        }
+    }
+
+    pub fn with_scale(self, scale: f64) -> Self {
+        Self { scale, ..self }
+    }
+
+    // The image covers the UV square with v = 0 at its bottom row
+    pub fn sample(&self, (u, v): (f64, f64)) -> Vec3<f64> {
+        if self.image.width() == 0 || self.image.height() == 0 {
+            return Vec3::new(0., 0., 0.);
+        }
+
+        // Pixel centers sit at half-integer coordinates
+        let x = u * self.scale * self.image.width() as f64 - 0.5;
+        let y = (1. - v * self.scale) * self.image.height() as f64 - 0.5;
+
+        match self.interpolation {
+            Interpolation::Nearest => self.pixel(x.round() as i64, y.round() as i64),
+            Interpolation::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = self.pixel(x0, y0) * (1. - tx) + self.pixel(x0 + 1, y0) * tx;
+                let bottom = self.pixel(x0, y0 + 1) * (1. - tx) + self.pixel(x0 + 1, y0 + 1) * tx;
+                top * (1. - ty) + bottom * ty
+            }
+        }
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Vec3<f64> {
+        let [r, g, b] = self
+            .image
+            .get_pixel(
+                self.wrap.apply(x, self.image.width()),
+                self.wrap.apply(y, self.image.height()),
+            )
+            .0;
+        Vec3::new(r as f64, g as f64, b as f64)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn color(&self, uv: (f64, f64), _point: Vec3<f64>) -> Vec3<f64> {
+        self.sample(uv)
+    }
+}
+
+// Where a procedural texture is evaluated
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mapping {
+    #[default]
+    Uv, // (u, v, 0)
+    Position, // The hit point in world space, for patterns that run through objects
+}
+
+// Each pattern picks a blend between the two colors of a procedural texture, 0 for the first
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Checker, // Unit cubes
+    Noise,
+    Turbulence { octaves: usize },
+    Marble { octaves: usize, distortion: f64 }, // Veins across x bent by turbulence
+    Wood { distortion: f64 },                   // Unit spaced rings around the z axis
+    Gradient { direction: Vec3<f64> },          // From 0 at the origin to 1 at direction
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Procedural {
+    pattern: Pattern,
+    colors: [Vec3<f64>; 2],
+    mapping: Mapping,
+    scale: f64, // Of the coordinates the pattern is evaluated at, larger values shrink it
+    perlin: Perlin,
+}
+
+impl Procedural {
+    pub fn new(pattern: Pattern, colors: [Vec3<f64>; 2]) -> Self {
+        Self {
+            pattern,
+            colors,
+            mapping: Mapping::default(),
+            scale: 1.,
+            perlin: Perlin::new(0),
+        }
+    }
+
+    pub fn with_mapping(self, mapping: Mapping) -> Self {
+        Self { mapping, ..self }
+    }
+
+    pub fn with_scale(self, scale: f64) -> Self {
+        Self { scale, ..self }
+    }
+
+    // Noise based patterns are fully determined by it
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            ..self
+        }
+    }
+
+    fn blend(&self, p: Vec3<f64>) -> f64 {
+        match self.pattern {
+            Pattern::Checker => {
+                let cells = p[0].floor() + p[1].floor() + p[2].floor();
+                cells.rem_euclid(2.)
+            }
+            Pattern::Noise => 0.5 * (1. + self.perlin.noise(p)),
+            Pattern::Turbulence { octaves } => self.perlin.turbulence(p, octaves),
+            Pattern::Marble {
+                octaves,
+                distortion,
+            } => {
+                let phase = p[0] + distortion * self.perlin.turbulence(p, octaves);
+                0.5 * (1. + (PI * phase).sin())
+            }
+            Pattern::Wood { distortion } => {
+                let radius = (p[0] * p[0] + p[1] * p[1]).sqrt();
+                (radius + distortion * self.perlin.noise(p)).rem_euclid(1.)
+            }
+            Pattern::Gradient { direction } => p * direction / (direction * direction),
+        }
+    }
+}
+
+impl Texture for Procedural {
+    fn color(&self, (u, v): (f64, f64), point: Vec3<f64>) -> Vec3<f64> {
+        let p = match self.mapping {
+            Mapping::Uv => Vec3::new(u, v, 0.),
+            Mapping::Position => point,
+        };
+        let t = self.blend(p * self.scale).clamp(0., 1.);
+        self.colors[0] * (1. - t) + self.colors[1] * t
+    }
+}
+
+fn srgb_to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
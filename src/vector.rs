@@ -1,6 +1,7 @@
 use std::ops::{Add, Index, Mul, Neg, Sub};
 
 use image::Rgb;
+use serde::{Deserialize, Deserializer};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Vec3<T> {
@@ -15,6 +16,14 @@ impl<T> Vec3<T> {
     }
 }
 
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Vec3<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Vectors are written as plain [x, y, z] arrays
+        let [x, y, z] = <[T; 3]>::deserialize(deserializer)?;
+        Ok(Self::new(x, y, z))
+    }
+}
+
 impl<T: Add<Output = T>> Add for Vec3<T> {
     type Output = Self;
 
@@ -77,6 +86,21 @@ impl<T: Copy + Mul<Output = T> + Sub<Output = T>> Cross for Vec3<T> {
     }
 }
 
+// Component-wise product, used to tint colors
+pub trait Hadamard {
+    type Output;
+
+    fn hadamard(&self, other: Self) -> Self::Output;
+}
+
+impl<T: Copy + Mul<Output = T>> Hadamard for Vec3<T> {
+    type Output = Self;
+
+    fn hadamard(&self, other: Self) -> Self {
+        Vec3::new(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+}
+
 impl<T: Neg<Output = T>> Neg for Vec3<T> {
     type Output = Self;
 
@@ -161,10 +185,13 @@ impl Normalize for Vec3<f64> {
 
 impl From<Vec3<f64>> for Rgb<u8> {
     fn from(orig: Vec3<f64>) -> Self {
-        Rgb::from([
-            (orig.x.min(1.) * 255.) as u8,
-            (orig.y.min(1.) * 255.) as u8,
-            (orig.z.min(1.) * 255.) as u8,
-        ])
+        let quantize = |channel: f64| (channel.clamp(0., 1.) * 255.).round() as u8;
+        Rgb::from([quantize(orig.x), quantize(orig.y), quantize(orig.z)])
+    }
+}
+
+impl From<Vec3<f64>> for Rgb<f32> {
+    fn from(orig: Vec3<f64>) -> Self {
+        Rgb::from([orig.x as f32, orig.y as f32, orig.z as f32])
     }
 }